@@ -0,0 +1,69 @@
+//! Per-tick cost of a small simulation, plus parse-every-time vs compiled-once evaluation.
+//! Run with `cargo bench --bench tick`.
+
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::interpreter::math::{evaluate_with, CompiledExpr, EvalOptions};
+use idc_runtime::runtime::value::Value;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const TICKS: u32 = 10_000;
+const EVALUATIONS: u32 = 100_000;
+
+const SCRIPT: &str = "\
+on start
+  load 0 as count to global
+  load {\"hp\":80,\"tags\":[\"a\",\"b\"]} as player to global
+every 1
+  load \"{{count}} + 1\" as count to global
+  if count % 2 == 0 and player.hp > 10
+    load \"clamp(count * 2, 0, 500)\" as doubled
+  unless player.tags contains 'c'
+    load \"length player.tags + count\" as total
+";
+
+const EXPRESSION: &str = "count % 7 == 3 and player.hp * 2 > 100 or max(count, 5) > 9000";
+
+fn report(label: &str, elapsed: Duration, runs: u32) {
+    println!("{:<28} {:>10.0} ns/op   ({} runs, {:?})", label, elapsed.as_nanos() as f64 / runs as f64, runs, elapsed);
+}
+
+fn bench_ticks() {
+    let mut runtime = Runtime::from_source(SCRIPT, false).expect("bench script should load");
+    // Measure the interpreter, not stdout or the journal's per-statement clones
+    runtime.set_tick_banner(false);
+    runtime.set_journaling(false);
+    runtime.run();
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        runtime.tick();
+    }
+    report("tick", start.elapsed(), TICKS);
+}
+
+fn bench_expression() {
+    let mut memory = HashMap::new();
+    memory.insert("count".to_string(), Value::Int(41));
+    memory.insert("player".to_string(), Value::from_literal(r#"{"hp":80}"#));
+    let options = EvalOptions::default();
+
+    let start = Instant::now();
+    for _ in 0..EVALUATIONS {
+        black_box(evaluate_with(black_box(EXPRESSION), &memory, &options).unwrap());
+    }
+    report("expression, parsed per call", start.elapsed(), EVALUATIONS);
+
+    let compiled = CompiledExpr::new(EXPRESSION);
+    let start = Instant::now();
+    for _ in 0..EVALUATIONS {
+        black_box(compiled.evaluate(&memory, &options).unwrap());
+    }
+    report("expression, compiled once", start.elapsed(), EVALUATIONS);
+}
+
+fn main() {
+    bench_ticks();
+    bench_expression();
+}
@@ -1,24 +1,90 @@
 use std::collections::{HashMap, VecDeque};
-use crate::parser::ast::AstNode;
+use std::fmt;
+use std::io::BufRead;
+use std::rc::Rc;
+use crate::parser::ast::{build_ast_from_reader, build_ast_from_str, AstBuildError, AstNode};
+use crate::parser::span::Span;
+use crate::parser::stmt::{lower, Block, LowerError, Query, ReflectTarget, Setting, Spanned, Stmt};
+use crate::interpreter::journal::{Guard, Journal, JournalEntry, Outcome, SkipCause};
+use crate::interpreter::math::{CompiledExpr, EvalOptions, OverflowPolicy, Template};
 use crate::utils::logger::Logger;
-use crate::runtime::memory::MemoryStore;
-use crate::utils::utils::resolve_key_or_expression;
+use crate::runtime::memory::{MemoryStore, ScopeTarget};
+use crate::runtime::promotion::{self, MergeStrategy, Promotion, PromotionPolicy};
+use crate::runtime::snapshot::{Flags, Snapshot, SnapshotError, SNAPSHOT_VERSION};
+use crate::runtime::value::Value;
 
-#[derive(Clone)]
+/// Handler bodies are shared so running one does not copy its statements
 struct PeriodicBlock {
     interval: u32,
-    node: AstNode,
+    body: Rc<Block>,
 }
 
+/// Why a script could not be loaded into the runtime
+#[derive(Debug)]
+pub enum LoadError {
+    Parse(AstBuildError),
+    Invalid(Vec<LowerError>),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Parse(e) => write!(f, "{}", e),
+            LoadError::Invalid(errors) => {
+                let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+        }
+    }
+}
+
+impl From<AstBuildError> for LoadError {
+    fn from(e: AstBuildError) -> Self {
+        LoadError::Parse(e)
+    }
+}
+
+/// Control signal returned by every executed statement; anything other than `Continue`
+/// unwinds the enclosing blocks up to the nearest construct that handles it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+    Break,
+    /// Raised by `continue`: skip the rest of the loop body and re-check the condition
+    NextIteration,
+    /// Raised by `rollback` or a failed statement inside `atomic`: abandon the block and its writes
+    Rollback,
+}
+
+/// Default cap on `while` iterations before the loop is abandoned with a warning
+pub const DEFAULT_MAX_ITERATIONS: u32 = 10_000;
+
 pub struct Runtime {
     memory: MemoryStore,
     logger: Logger,
-    event_registry: HashMap<String, AstNode>,
-    execution_queue: VecDeque<AstNode>,
+    event_registry: HashMap<String, Rc<Block>>,
+    execution_queue: VecDeque<Spanned<Stmt>>,
     every_blocks: Vec<PeriodicBlock>,
     tick_counter: u32,
     event_stack: Vec<String>,
     promote_on_tick: bool,
+    source_name: Option<String>,
+    responses: Vec<String>,
+    max_iterations: u32,
+    precision: Option<usize>,
+    eval_options: EvalOptions,
+    journal: Journal,
+    /// Whether executed and skipped statements are recorded in the journal
+    journaling: bool,
+    /// Whether each tick prints a `[TICK n]` line to stdout
+    tick_banner: bool,
+    /// Conditions enclosing the statement currently executing
+    guards: Vec<Guard>,
+    /// The `stop` / `break` / `continue` behind the flow signal currently unwinding
+    unwound_by: Option<(String, Span)>,
+    /// Statements that failed to evaluate so far; an `atomic` block rolls back when it grows
+    failures: u32,
 }
 
 impl Runtime {
@@ -35,9 +101,43 @@ impl Runtime {
             tick_counter: 0,
             event_stack: Vec::new(),
             promote_on_tick: true,
+            source_name: None,
+            responses: Vec::new(),
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            precision: None,
+            eval_options: EvalOptions::default(),
+            journal: Journal::new(),
+            journaling: true,
+            tick_banner: true,
+            guards: Vec::new(),
+            unwound_by: None,
+            failures: 0,
         }
     }
 
+    /// Builds a runtime with the given in-memory script already loaded
+    pub fn from_source(source: &str, trace_enabled: bool) -> Result<Self, LoadError> {
+        let mut runtime = Runtime::new(trace_enabled);
+        runtime.load_script(build_ast_from_str(source)?).map_err(LoadError::Invalid)?;
+        Ok(runtime)
+    }
+
+    /// Builds a runtime with a script read from any buffered reader already loaded
+    pub fn from_reader<R: BufRead>(reader: R, trace_enabled: bool) -> Result<Self, LoadError> {
+        let mut runtime = Runtime::new(trace_enabled);
+        runtime.load_script(build_ast_from_reader(reader)?).map_err(LoadError::Invalid)?;
+        Ok(runtime)
+    }
+
+    /// Names the script (usually its path) so runtime messages read `script.idc:12:5: ...`
+    pub fn set_source_name(&mut self, name: &str) {
+        self.source_name = Some(name.to_string());
+    }
+
+    fn location(&self, span: Span) -> String {
+        span.describe(self.source_name.as_deref())
+    }
+
     pub fn set_promote_on_tick(&mut self, value: bool) {
         self.promote_on_tick = value;
         self.memory.set_promote_on_tick(value, Some(&mut self.logger));
@@ -48,72 +148,265 @@ impl Runtime {
         self.set_promote_on_tick(value);
     }
 
+    /// Caps how many times a single `while` loop may run before it is abandoned
+    pub fn set_max_iterations(&mut self, limit: u32) {
+        self.logger.debug(&format!("Set max_iterations = {}", limit));
+        self.max_iterations = limit;
+    }
+
+    /// Turns journal recording on or off; with it off, `why:` and `explain:` only know
+    /// about statements that ran while it was on
+    pub fn set_journaling(&mut self, value: bool) {
+        self.logger.debug(&format!("Set journaling = {}", value));
+        self.journaling = value;
+    }
+
+    /// Turns the `[TICK n]` line printed at the start of every tick on or off
+    pub fn set_tick_banner(&mut self, value: bool) {
+        self.logger.debug(&format!("Set tick_banner = {}", value));
+        self.tick_banner = value;
+    }
+
+    /// Rounds floats printed by `respond_event` to a fixed number of decimal places
+    pub fn set_precision(&mut self, places: Option<usize>) {
+        self.logger.debug(&format!("Set precision = {:?}", places));
+        self.precision = places;
+    }
+
+    /// Chooses what integer arithmetic does when a result does not fit in an `i64`
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.logger.debug(&format!("Set overflow = {}", policy));
+        self.eval_options.overflow = policy;
+    }
+
+    /// Makes a host function callable from expressions as `name(arg, ...)`; a built-in of
+    /// the same name is replaced
+    pub fn register_function<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.logger.debug(&format!("Registered function '{}'", name));
+        self.eval_options.functions.register(name, function);
+    }
+
+    /// Makes unresolved placeholders runtime errors instead of reading them as 0
+    pub fn set_strict(&mut self, value: bool) {
+        self.logger.debug(&format!("Set strict = {}", value));
+        self.eval_options.strict = value;
+    }
+
+    /// Replaces the promotion policy: which keys promotions carry and how they merge
+    pub fn set_promotion_policy(&mut self, policy: PromotionPolicy) {
+        self.logger.debug(&format!("Set promotion policy = {:?}", policy));
+        self.memory.set_promotion_policy(policy);
+    }
+
+    /// Limits promotions to keys matching one of `keys`; an empty list allows every key
+    pub fn set_promote_allow(&mut self, keys: Vec<String>) {
+        let mut policy = self.memory.promotion_policy().clone();
+        policy.allow = keys;
+        self.set_promotion_policy(policy);
+    }
+
+    /// Keeps keys matching any of `keys` out of every promotion
+    pub fn set_promote_deny(&mut self, keys: Vec<String>) {
+        let mut policy = self.memory.promotion_policy().clone();
+        policy.deny = keys;
+        self.set_promotion_policy(policy);
+    }
+
+    /// Chooses how promoted keys merge with values the destination already holds; with
+    /// `keys`, only for keys matching them, replacing any earlier strategy for those patterns
+    pub fn set_promote_merge(&mut self, strategy: MergeStrategy, keys: Vec<String>) {
+        let mut policy = self.memory.promotion_policy().clone();
+        if keys.is_empty() {
+            policy.merge = strategy;
+        } else {
+            policy.rules.retain(|(pattern, _)| !keys.contains(pattern));
+            policy.rules.extend(keys.into_iter().map(|key| (key, strategy)));
+        }
+        self.set_promotion_policy(policy);
+    }
+
+    /// Evaluates an expression or literal against visible memory, logging any warnings the
+    /// evaluation raised (such as a saturated overflow) against `span`
+    fn evaluate(&mut self, expr: &CompiledExpr, span: Option<Span>) -> Result<Value, String> {
+        let evaluation = expr.evaluate(&self.memory, &self.eval_options).inspect_err(|_| self.failures += 1)?;
+        self.log_notes(evaluation.warnings, evaluation.substitutions, span);
+        Ok(evaluation.value)
+    }
+
+    /// Fills in the `{{...}}` placeholders of `text`, logging notes as `evaluate` does; fails
+    /// only in strict mode
+    fn render(&mut self, text: &Template, span: Span) -> Result<String, String> {
+        let rendered = text.render(&self.memory, &self.eval_options).inspect_err(|_| self.failures += 1)?;
+        self.log_notes(rendered.warnings, rendered.substitutions, Some(span));
+        Ok(rendered.text)
+    }
+
+    /// Warnings go to the warning log; lenient placeholder substitutions to the trace
+    fn log_notes(&mut self, warnings: Vec<String>, substitutions: Vec<String>, span: Option<Span>) {
+        let at = span.map(|span| format!("{}: ", self.location(span))).unwrap_or_default();
+        for warning in warnings {
+            self.logger.warn(&format!("{}[MATH] {}", at, warning));
+        }
+        for substitution in substitutions {
+            self.logger.trace(&format!("{}[PLACEHOLDER] {}", at, substitution));
+        }
+    }
+
+    /// Logs a promotion, prefixed with the location of the statement that ran it
+    fn log_promotions(&mut self, promotions: &[Promotion], span: Option<Span>) {
+        let at = span.map(|span| format!("{}: ", self.location(span))).unwrap_or_default();
+        promotion::log_promotions(promotions, &at, &mut self.logger);
+    }
+
+    /// Captures memory, tick position and settings so a run can be resumed later
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            scopes: self.memory.scopes().to_vec(),
+            tick: self.tick_counter,
+            event_stack: self.event_stack.clone(),
+            flags: Flags {
+                promote_on_tick: self.promote_on_tick,
+                max_iterations: self.max_iterations,
+                precision: self.precision,
+                overflow: self.eval_options.overflow,
+                strict: self.eval_options.strict,
+                promotion: self.memory.promotion_policy().clone(),
+            },
+        }
+    }
+
+    /// Resumes from a snapshot: restores global memory, the tick counter and settings. The
+    /// handler scopes and event stack a snapshot records are dropped, since no handler is
+    /// running once it is restored; call it between ticks. Handlers come from the loaded
+    /// script, not the snapshot
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), SnapshotError> {
+        let Some(global) = snapshot.scopes.into_iter().next().filter(|scope| scope.name == "global") else {
+            return Err(SnapshotError::MissingGlobal);
+        };
+        self.memory.close_handler_scopes();
+        self.event_stack.clear();
+        self.memory.set_origin(snapshot.tick, None);
+        self.memory.restore_global(global.data, "snapshot");
+        self.restore_progress(snapshot.tick, snapshot.flags);
+        Ok(())
+    }
+
+    fn restore_progress(&mut self, tick: u32, flags: Flags) {
+        self.tick_counter = tick;
+        // Only the runtime's flag: the memory store's own promotion on scope exit stays as it is
+        self.promote_on_tick = flags.promote_on_tick;
+        self.set_max_iterations(flags.max_iterations);
+        self.set_precision(flags.precision);
+        self.set_overflow_policy(flags.overflow);
+        self.set_strict(flags.strict);
+        self.set_promotion_policy(flags.promotion);
+        self.logger.debug(&format!("[SNAPSHOT] Restored at tick {}", tick));
+    }
+
     pub fn promote_memory_if(&mut self, condition: &str) {
-        match resolve_key_or_expression(condition, &self.memory) {
-            Ok(val) if val != "0" => {
+        match self.evaluate(&CompiledExpr::new(condition), None) {
+            Ok(val) if val.is_truthy() => {
                 self.logger.debug(&format!("[PROMOTE] condition '{}' => true", condition));
-                self.memory.promote_all();
+                let promotions = self.memory.promote_all();
+                self.log_promotions(&promotions, None);
             }
             Ok(_) => self.logger.debug(&format!("[PROMOTE] condition '{}' => false", condition)),
             Err(e) => self.logger.warn(&format!("[PROMOTE] Failed to evaluate condition '{}': {}", condition, e)),
         }
     }
 
-    pub fn load_script(&mut self, ast_nodes: Vec<AstNode>) {
-        for node in ast_nodes {
-            match node.node_type.as_str() {
-                "on" => {
-                    self.logger.debug(&format!("[EVENT] Registered event '{}'", node.value));
-                    self.event_registry.insert(node.value.clone(), node);
+    /// Lowers the AST into typed statements and registers handlers; a script with any
+    /// malformed statement is rejected as a whole
+    pub fn load_script(&mut self, ast_nodes: Vec<AstNode>) -> Result<(), Vec<LowerError>> {
+        for stmt in lower(&ast_nodes)? {
+            match stmt.node {
+                Stmt::On { event, body } => {
+                    self.logger.debug(&format!("[EVENT] Registered event '{}'", event));
+                    self.event_registry.insert(event, Rc::new(body));
                 }
-                "every" => {
-                    if let Ok(interval) = node.value.parse::<u32>() {
-                        self.every_blocks.push(PeriodicBlock { interval, node });
-                    } else {
-                        self.logger.warn(&format!("Invalid interval in 'every {}'", node.value));
-                    }
+                Stmt::Every { interval, body } => {
+                    self.every_blocks.push(PeriodicBlock { interval, body: Rc::new(body) });
                 }
-                "set" => {
-                    if node.value == "promote_always" {
-                        if let Some(flag) = node.modifiers.get(0) {
-                            let value = flag.to_lowercase() == "true";
-                            self.set_promote_always(value);
-                        }
-                    }
-                }
-                _ => {
-                    self.execution_queue.push_back(node);
+                Stmt::Set(setting) => self.apply_setting(&setting),
+                node => {
+                    self.execution_queue.push_back(Spanned { node, span: stmt.span });
                 }
             }
         }
+        Ok(())
+    }
+
+    fn apply_setting(&mut self, setting: &Setting) {
+        match setting {
+            Setting::PromoteAlways(value) => self.set_promote_always(*value),
+            Setting::MaxIterations(limit) => self.set_max_iterations(*limit),
+            Setting::Precision(places) => self.set_precision(*places),
+            Setting::Overflow(policy) => self.set_overflow_policy(*policy),
+            Setting::Strict(value) => self.set_strict(*value),
+            Setting::PromoteAllow(keys) => self.set_promote_allow(keys.clone()),
+            Setting::PromoteDeny(keys) => self.set_promote_deny(keys.clone()),
+            Setting::PromoteMerge { strategy, keys } => self.set_promote_merge(*strategy, keys.clone()),
+        }
     }
 
     pub fn run(&mut self) {
         self.logger.info("Runtime started.");
         self.trigger_event("start");
-        while let Some(node) = self.execution_queue.pop_front() {
-            self.execute_node(&node);
+        while let Some(stmt) = self.execution_queue.pop_front() {
+            if self.execute_node(&stmt) != Flow::Continue {
+                let at = self.location(stmt.span);
+                self.logger.trace(&format!(
+                    "{}: [FLOW] stop at top level; dropping {} queued statement(s)",
+                    at,
+                    self.execution_queue.len()
+                ));
+                let dropped: Block = self.execution_queue.drain(..).collect();
+                let (keyword, span) = self.unwound_by.clone().unwrap_or(("stop".to_string(), stmt.span));
+                self.skip_block(&dropped, &SkipCause::Unwound { keyword, span });
+            }
         }
     }
 
+    /// Everything `respond` and `respond_event` produced so far, in order
+    pub fn responses(&self) -> &[String] {
+        &self.responses
+    }
+
+    /// Structured record of what ran, what was skipped, and why
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Answers a `why:` or `explain:` question about what has run so far
+    pub fn query(&self, text: &str) -> Result<String, String> {
+        let query = Query::parse(text)?;
+        Ok(self.journal.answer(&query, &self.memory, self.source_name.as_deref()))
+    }
+
     pub fn tick(&mut self) {
         self.tick_counter += 1;
-        println!("[TICK {}]", self.tick_counter);
+        if self.tick_banner {
+            println!("[TICK {}]", self.tick_counter);
+        }
         self.logger.debug(&format!("Tick #{}", self.tick_counter));
-        let periodic = self.every_blocks.clone();
 
-        for block in periodic {
-            if self.tick_counter % block.interval == 0 {
-                self.logger.trace(&format!("Tick matched interval: {}", block.interval));
+        for index in 0..self.every_blocks.len() {
+            let interval = self.every_blocks[index].interval;
+            if self.tick_counter.is_multiple_of(interval) {
+                self.logger.trace(&format!("Tick matched interval: {}", interval));
                 let scope_name = format!("tick:{}", self.tick_counter);
                 self.memory.enter_scope(&scope_name, Some(&mut self.logger));
 
-                for child in &block.node.children {
-                    self.execute_node(child);
-                }
+                let body = Rc::clone(&self.every_blocks[index].body);
+                self.execute_block(&body);
 
                 if self.promote_on_tick {
                     self.logger.trace("[PROMOTE] From tick scope to global...");
+                    self.memory.set_origin(self.tick_counter, None);
                     self.memory.promote_scope(&scope_name, "global", Some(&mut self.logger));
                 }
 
@@ -128,10 +421,26 @@ impl Runtime {
     }
 
     pub fn promote_memory(&mut self) {
-        self.memory.promote_all();
+        let promotions = self.memory.promote_all();
+        self.log_promotions(&promotions, None);
+    }
+
+    fn bind_payload(&mut self, payload: &[(String, Value)]) {
+        for (key, value) in payload {
+            self.memory.set(key, value.clone(), Some(&mut self.logger));
+        }
     }
 
     pub fn trigger_event(&mut self, event_name: &str) {
+        self.trigger_event_with(event_name, &[]);
+    }
+
+    /// Triggers an event, binding each payload entry into the handler's `event:<name>` scope
+    pub fn trigger_event_with(&mut self, event_name: &str, payload: &[(String, Value)]) {
+        if self.event_stack.is_empty() {
+            // Called by the host rather than a `trigger` statement
+            self.memory.set_origin(self.tick_counter, None);
+        }
         if self.event_stack.contains(&event_name.to_string()) {
             self.logger.warn(&format!(
                 "Skipping recursive event '{}': already in call stack {:?}",
@@ -140,20 +449,22 @@ impl Runtime {
             return;
         }
 
-        if let Some(node) = self.event_registry.get(event_name).cloned() {
+        if let Some(body) = self.event_registry.get(event_name).map(Rc::clone) {
             self.logger.trace(&format!("Triggering event '{}'", event_name));
             self.logger.trace(&format!("Call stack before: {:?}", self.event_stack));
 
             let scope_name = format!("event:{}", event_name);
             self.memory.enter_scope(&scope_name, Some(&mut self.logger));
+            self.bind_payload(payload);
             self.event_stack.push(event_name.to_string());
 
-            for child in &node.children {
-                self.execute_node(child);
+            if self.execute_block(&body) != Flow::Continue {
+                self.logger.trace(&format!("[FLOW] Handler '{}' stopped early", event_name));
             }
 
             if event_name == "start" && self.promote_on_tick {
                 self.logger.trace("[PROMOTE] From start event to global...");
+                self.memory.set_origin(self.tick_counter, None);
                 self.memory.promote_scope(&scope_name, "global", Some(&mut self.logger));
             }
 
@@ -163,17 +474,16 @@ impl Runtime {
         } else {
             self.logger.warn(&format!("Attempted to trigger unknown event '{}'", event_name));
 
-            if let Some(missing_node) = self.event_registry.get("missing").cloned() {
+            if let Some(missing_body) = self.event_registry.get("missing").map(Rc::clone) {
                 self.logger.warn(&format!("Falling back to 'on missing' handler for '{}'", event_name));
 
                 let scope_name = format!("event:missing:{}", event_name);
                 self.memory.enter_scope(&scope_name, Some(&mut self.logger));
-                self.memory.set("event_name", event_name, Some(&mut self.logger));
+                self.memory.set("event_name", Value::from(event_name), Some(&mut self.logger));
+                self.bind_payload(payload);
                 self.event_stack.push("missing".to_string());
 
-                for child in &missing_node.children {
-                    self.execute_node(child);
-                }
+                self.execute_block(&missing_body);
 
                 self.event_stack.pop();
                 self.memory.exit_scope(Some(&mut self.logger));
@@ -183,107 +493,389 @@ impl Runtime {
         }
     }
 
-    fn execute_node(&mut self, node: &AstNode) {
-        self.logger.trace(&format!("Executing node: {} '{}'", node.node_type, node.value));
+    /// Runs statements in order until one of them signals something other than `Continue`
+    fn execute_block(&mut self, block: &Block) -> Flow {
+        for (index, stmt) in block.iter().enumerate() {
+            let failures = self.failures;
+            let mut flow = self.execute_node(stmt);
+            // Inside a transaction the first failed statement abandons the block
+            if flow == Flow::Continue && self.failures > failures && self.memory.in_transaction() {
+                self.unwound_by = Some((stmt.node.keyword().to_string(), stmt.span));
+                flow = Flow::Rollback;
+            }
+            if flow != Flow::Continue {
+                let (keyword, span) = self
+                    .unwound_by
+                    .clone()
+                    .unwrap_or_else(|| (stmt.node.keyword().to_string(), stmt.span));
+                self.skip_block(&block[index + 1..], &SkipCause::Unwound { keyword, span });
+                return flow;
+            }
+        }
+        Flow::Continue
+    }
+
+    fn journal_entry(&self, stmt: &Spanned<Stmt>, outcome: Outcome) -> JournalEntry {
+        JournalEntry {
+            span: stmt.span,
+            statement: stmt.node.to_string(),
+            outcome,
+            scope_chain: self.memory.format_scope_chain(),
+            guards: self.guards.clone(),
+            response: None,
+            tick: self.tick_counter,
+        }
+    }
+
+    /// Journals every statement in a block that will not run, including nested bodies
+    fn skip_block(&mut self, block: &[Spanned<Stmt>], cause: &SkipCause) {
+        if !self.journaling {
+            return;
+        }
+        for stmt in block {
+            let entry = self.journal_entry(stmt, Outcome::Skipped(cause.clone()));
+            self.journal.record(entry);
+            match &stmt.node {
+                Stmt::If { then, else_, .. } => {
+                    self.skip_block(then, cause);
+                    self.skip_block(else_, cause);
+                }
+                Stmt::While { body, .. }
+                | Stmt::On { body, .. }
+                | Stmt::Every { body, .. }
+                | Stmt::Atomic { body }
+                | Stmt::Action { body, .. } => self.skip_block(body, cause),
+                _ => {}
+            }
+        }
+    }
+
+    fn execute_node(&mut self, stmt: &Spanned<Stmt>) -> Flow {
+        let at = self.location(stmt.span);
+        self.logger.trace(&format!("{}: Executing node: {}", at, stmt.node));
+
+        self.memory.set_origin(self.tick_counter, Some(stmt.span));
 
-        match node.node_type.as_str() {
-            "if" | "unless" => {
-                match resolve_key_or_expression(&node.value, &self.memory) {
+        // Queries are left out so they never answer about themselves
+        let entry = match stmt.node {
+            Stmt::Query(_) => None,
+            _ if !self.journaling => None,
+            _ => Some(self.journal.record(self.journal_entry(stmt, Outcome::Executed))),
+        };
+
+        match &stmt.node {
+            Stmt::If { cond, then, else_ } => {
+                match self.evaluate(&cond.expr, Some(stmt.span)) {
                     Ok(result) => {
-                        let should_run = if node.node_type == "if" { result != "0" } else { result == "0" };
-                        self.logger.trace(&format!("[EVAL] '{}' => {}", node.value, result));
-                        if should_run {
-                            for child in &node.children {
-                                self.execute_node(child);
-                            }
-                        }
+                        let should_run = result.is_truthy() != cond.negated;
+                        self.logger.trace(&format!("{}: [EVAL] '{}' => {}", at, cond.expr, result));
+                        let guard = Guard {
+                            header: stmt.node.to_string(),
+                            value: result.to_string(),
+                            held: should_run,
+                            span: stmt.span,
+                        };
+                        let (branch, passed_over) = if should_run { (then, else_) } else { (else_, then) };
+                        self.skip_block(passed_over, &SkipCause::Condition(guard.clone()));
+                        self.guards.push(guard);
+                        let flow = self.execute_block(branch);
+                        self.guards.pop();
+                        return flow;
+                    }
+                    Err(err) => {
+                        self.logger.warn(&format!("{}: [EVAL] Failed: {}", at, err));
+                        let cause = SkipCause::ConditionFailed {
+                            header: stmt.node.to_string(),
+                            error: err,
+                            span: stmt.span,
+                        };
+                        self.skip_block(then, &cause);
+                        self.skip_block(else_, &cause);
                     }
-                    Err(err) => self.logger.warn(&format!("[EVAL] Failed: {}", err)),
                 }
             }
 
-            "respond" => {
-                let full_text = std::iter::once(&node.value).chain(&node.modifiers).cloned().collect::<Vec<String>>().join(" ");
-                let rendered = self.memory.resolve_placeholders(&full_text);
-                self.logger.trace(&format!("Respond output: '{}' from scope [{}]", rendered, self.memory.format_scope_chain()));
+            Stmt::While { cond, body } => return self.execute_while(stmt.span, cond, body),
+
+            Stmt::Atomic { body } => return self.execute_atomic(stmt.span, body),
+
+            Stmt::Rollback => {
+                self.unwound_by = Some(("rollback".to_string(), stmt.span));
+                return Flow::Rollback;
+            }
+
+            Stmt::Break => {
+                self.unwound_by = Some(("break".to_string(), stmt.span));
+                return Flow::Break;
+            }
+
+            Stmt::Continue => {
+                self.unwound_by = Some(("continue".to_string(), stmt.span));
+                return Flow::NextIteration;
+            }
+
+            Stmt::Query(query) => {
+                let answer = self.journal.answer(query, &self.memory, self.source_name.as_deref());
+                self.logger.trace(&format!("{}: [QUERY] {}", at, query));
+                println!("{}", answer);
+                self.responses.push(answer);
+            }
+
+            Stmt::Respond { text } => {
+                let rendered = match self.render(text, stmt.span) {
+                    Ok(rendered) => rendered,
+                    Err(err) => {
+                        self.logger.error(&format!("{}: [STRICT] respond not sent: {}", at, err));
+                        return Flow::Continue;
+                    }
+                };
+                self.logger.trace(&format!("{}: Respond output: '{}' from scope [{}]", at, rendered, self.memory.format_scope_chain()));
                 println!("{}", rendered);
+                if let Some(index) = entry {
+                    self.journal.set_response(index, &rendered);
+                }
+                self.responses.push(rendered);
             }
 
-            "respond_event" => {
-                match resolve_key_or_expression(&node.value, &self.memory) {
+            Stmt::RespondEvent { expr } => {
+                match self.evaluate(expr, Some(stmt.span)) {
                     Ok(result) => {
-                        self.logger.trace(&format!("Respond_event: '{}' => {} from [{}]", node.value, result, self.memory.format_scope_chain()));
+                        self.logger.trace(&format!("{}: Respond_event: '{}' => {} from [{}]", at, expr, result, self.memory.format_scope_chain()));
+                        let result = match (result, self.precision) {
+                            (Value::Float(x), Some(places)) => format!("{:.*}", places, x),
+                            (result, _) => result.to_string(),
+                        };
                         println!("{}", result);
+                        if let Some(index) = entry {
+                            self.journal.set_response(index, &result);
+                        }
+                        self.responses.push(result);
+                    }
+                    Err(err) => self.logger.warn(&format!("{}: Evaluation failed: {}", at, err)),
+                }
+            }
+
+            Stmt::Trigger { event, payload } => {
+                let event_name = match self.render(event, stmt.span) {
+                    Ok(name) => name.trim_matches('"').to_string(),
+                    Err(err) => {
+                        self.logger.error(&format!("{}: [STRICT] trigger not sent: {}", at, err));
+                        return Flow::Continue;
+                    }
+                };
+                let mut values = Vec::with_capacity(payload.len());
+                for (key, expr) in payload {
+                    match self.evaluate(expr, Some(stmt.span)) {
+                        Ok(value) => values.push((key.clone(), value)),
+                        Err(err) => {
+                            self.logger.warn(&format!("{}: [EVENT] Payload '{}' failed: {}", at, key, err));
+                            values.push((key.clone(), Value::from(expr.source().trim_matches('"'))));
+                        }
                     }
-                    Err(err) => self.logger.warn(&format!("Evaluation failed: {}", err)),
                 }
+                self.logger.trace(&format!("{}: Triggering event via 'trigger': {}", at, event_name));
+                self.trigger_event_with(&event_name, &values);
             }
 
-            "trigger" => {
-                let event_name = self.memory.resolve_placeholders(&node.value).trim_matches('"').to_string();
-                self.logger.trace(&format!("Triggering event via 'trigger': {}", event_name));
-                self.trigger_event(&event_name);
+            Stmt::Load { expr, target, scope } => {
+                match self.evaluate(expr, Some(stmt.span)) {
+                    Ok(result) => {
+                        let label = scope.as_ref().unwrap_or(&ScopeTarget::Local);
+                        self.logger.trace(&format!("{}: [LOAD] Target scope: {}. Set {} = {}", at, label, target, result));
+                        if let Err(err) = self.memory.set_target(target, result, scope.as_ref(), Some(&mut self.logger)) {
+                            self.failures += 1;
+                            self.logger.warn(&format!("{}: [LOAD] {} not set: {}", at, target, err));
+                        }
+                    }
+                    Err(err) => {
+                        self.logger.warn(&format!("{}: [LOAD] Failed to evaluate '{}': {}", at, expr, err));
+                        self.memory.set(target, Value::from(expr.source()), Some(&mut self.logger));
+                    }
+                }
             }
 
-            "load" => {
-                let mut resolved_key = &node.value;
-                let mut dest_scope = None;
-                let mut i = 0;
-                while i < node.modifiers.len() {
-                    if node.modifiers[i] == "as" {
-                        if let Some(k) = node.modifiers.get(i + 1) {
-                            resolved_key = k;
-                            i += 1;
+            Stmt::Promote { keys, target, when } => {
+                if let Some(cond) = when {
+                    match self.evaluate(cond, Some(stmt.span)) {
+                        Ok(value) if value.is_truthy() => {}
+                        Ok(value) => {
+                            self.logger.trace(&format!("{}: [PROMOTE] '{}' => {}; nothing promoted", at, cond, value));
+                            return Flow::Continue;
                         }
-                    } else if node.modifiers[i] == "to" {
-                        if let Some(s) = node.modifiers.get(i + 1) {
-                            if s == "global" {
-                                dest_scope = Some("global");
-                            }
-                            i += 1;
+                        Err(err) => {
+                            self.logger.warn(&format!("{}: [PROMOTE] Failed to evaluate condition: {}", at, err));
+                            return Flow::Continue;
                         }
                     }
-                    i += 1;
                 }
-                match resolve_key_or_expression(&node.value, &self.memory) {
-                    Ok(result) => {
-                        let label = dest_scope.unwrap_or("local");
-                        self.logger.trace(&format!("[LOAD] Target scope: {}. Set {} = {}", label, resolved_key, result));
-                        self.memory.set_target(resolved_key, &result, dest_scope, Some(&mut self.logger));
+                let from = self.memory.scopes().len() - 1;
+                match self.memory.resolve(target) {
+                    Ok(to) if to < from => {
+                        let promotions = self.memory.promote_between(from, to, Some(keys));
+                        if promotions.is_empty() {
+                            self.logger.trace(&format!("{}: [PROMOTE] No key in [{}] matches {}", at, self.memory.scopes()[from].name, keys.join(", ")));
+                        }
+                        self.log_promotions(&promotions, Some(stmt.span));
+                    }
+                    Ok(_) => self.logger.warn(&format!("{}: [PROMOTE] [{}] is not outside the current scope; nothing promoted", at, target)),
+                    Err(err) => {
+                        self.failures += 1;
+                        self.logger.warn(&format!("{}: [PROMOTE] Nothing promoted: {}", at, err));
+                    }
+                }
+            }
+
+            Stmt::SaveMemory { path } => {
+                let path = match self.render(path, stmt.span) {
+                    Ok(path) => path,
+                    Err(err) => {
+                        self.logger.error(&format!("{}: [STRICT] save not written: {}", at, err));
+                        return Flow::Continue;
+                    }
+                };
+                match self.snapshot().save_to(&path) {
+                    Ok(()) => self.logger.trace(&format!("{}: [SNAPSHOT] Saved to '{}'", at, path)),
+                    Err(err) => {
+                        self.failures += 1;
+                        self.logger.warn(&format!("{}: [SNAPSHOT] Could not save to '{}': {}", at, path, err));
+                    }
+                }
+            }
+
+            // The running handler keeps its own scopes and call stack; only global memory,
+            // the tick counter and settings are taken from the file
+            Stmt::LoadMemory { path } => {
+                let path = match self.render(path, stmt.span) {
+                    Ok(path) => path,
+                    Err(err) => {
+                        self.logger.error(&format!("{}: [STRICT] load not read: {}", at, err));
+                        return Flow::Continue;
+                    }
+                };
+                match Snapshot::load_from(&path) {
+                    Ok(mut snapshot) => {
+                        let global = snapshot.scopes.swap_remove(0).data;
+                        self.memory.restore_global(global, &path);
+                        self.restore_progress(snapshot.tick, snapshot.flags);
+                        self.logger.trace(&format!("{}: [SNAPSHOT] Loaded from '{}'", at, path));
                     }
                     Err(err) => {
-                        self.logger.warn(&format!("[LOAD] Failed to evaluate '{}': {}", node.value, err));
-                        let fallback_key = node.modifiers.get(0).unwrap_or(&node.value);
-                        self.memory.set_target(fallback_key, &node.value, None, Some(&mut self.logger));
+                        self.failures += 1;
+                        self.logger.warn(&format!("{}: [SNAPSHOT] Could not load '{}': {}", at, path, err));
                     }
                 }
             }
 
-            "remember" => {
-                self.memory.set(&node.value, "0", Some(&mut self.logger));
+            Stmt::Remember { key } => {
+                self.memory.set(key, Value::Int(0), Some(&mut self.logger));
             }
 
-            "wait" => {
+            Stmt::Wait => {
                 std::thread::sleep(std::time::Duration::from_secs(1));
             }
 
-            "reflect" => match node.value.as_str() {
-                "memory" => self.reflect_memory(node.modifiers.get(0).map(|s| s.as_str()).unwrap_or("flat")),
-                "stack" => self.reflect_stack(),
-                "log" => self.reflect_log(),
-                "event" => self.reflect_event(),
-                "flags" => self.reflect_flags(),
-                _ => {}
+            Stmt::Reflect { target, mode } => match target {
+                ReflectTarget::Memory => self.reflect_memory(mode.as_deref().unwrap_or("flat")),
+                ReflectTarget::Stack => self.reflect_stack(),
+                ReflectTarget::Log => self.reflect_log(),
+                ReflectTarget::Event => self.reflect_event(),
+                ReflectTarget::Flags => self.reflect_flags(),
             },
 
-            "stop" => {
-                return;
+            Stmt::Set(setting) => self.apply_setting(setting),
+
+            Stmt::Stop => {
+                self.logger.trace(&format!("{}: [FLOW] stop", at));
+                self.unwound_by = Some(("stop".to_string(), stmt.span));
+                return Flow::Stop;
+            }
+
+            Stmt::On { body, .. } | Stmt::Every { body, .. } | Stmt::Action { body, .. } => {
+                return self.execute_block(body);
             }
+        }
 
-            _ => {
-                for child in &node.children {
-                    self.execute_node(child);
+        Flow::Continue
+    }
+
+    /// Runs `body` as a transaction: its memory writes, including those of events it
+    /// triggers, are kept only if it finishes without `stop`, `rollback` or a failed
+    /// statement. Responses already sent are not taken back
+    fn execute_atomic(&mut self, span: Span, body: &Block) -> Flow {
+        let at = self.location(span);
+        self.memory.begin();
+        let flow = self.execute_block(body);
+
+        if !matches!(flow, Flow::Stop | Flow::Rollback) {
+            self.memory.commit();
+            self.logger.trace(&format!("{}: [ATOMIC] committed", at));
+            return flow;
+        }
+
+        let restored = self.memory.rollback();
+        let (keyword, cause) = self.unwound_by.clone().unwrap_or_else(|| ("atomic".to_string(), span));
+        let message = format!(
+            "{}: [ATOMIC] rolled back {} change(s) because `{}` at {} left the block",
+            at, restored, keyword, cause
+        );
+        if keyword == "stop" || keyword == "rollback" {
+            self.logger.trace(&message);
+        } else {
+            self.logger.warn(&message);
+        }
+
+        // `stop` still ends the handler; a rollback ends only the atomic block
+        if flow == Flow::Stop { Flow::Stop } else { Flow::Continue }
+    }
+
+    /// Re-evaluates the condition before every pass; `break` ends the loop, `continue` skips
+    /// to the next check, and `stop` propagates to the enclosing handler
+    fn execute_while(&mut self, span: Span, cond: &CompiledExpr, body: &Block) -> Flow {
+        let at = self.location(span);
+        let mut iterations = 0;
+
+        loop {
+            match self.evaluate(cond, Some(span)) {
+                Ok(result) => {
+                    self.logger.trace(&format!("{}: [EVAL] while '{}' => {}", at, cond, result));
+                    let held = result.is_truthy();
+                    let guard = Guard { header: format!("while {}", cond), value: result.to_string(), held, span };
+                    if !held {
+                        if iterations == 0 {
+                            self.skip_block(body, &SkipCause::Condition(Guard { held: false, ..guard }));
+                        }
+                        return Flow::Continue;
+                    }
+                    self.guards.push(guard);
                 }
+                Err(err) => {
+                    self.logger.warn(&format!("{}: [EVAL] Failed: {}", at, err));
+                    if iterations == 0 {
+                        let header = format!("while {}", cond);
+                        self.skip_block(body, &SkipCause::ConditionFailed { header, error: err, span });
+                    }
+                    return Flow::Continue;
+                }
+            }
+
+            if iterations >= self.max_iterations {
+                self.guards.pop();
+                self.logger.warn(&format!(
+                    "{}: [LOOP] 'while {}' was still true after {} iterations; leaving the loop. \
+                     Raise the cap with `set max_iterations <n>` if this is intended.",
+                    at, cond, self.max_iterations
+                ));
+                return Flow::Continue;
+            }
+            iterations += 1;
+
+            let flow = self.execute_block(body);
+            self.guards.pop();
+            match flow {
+                Flow::Break => return Flow::Continue,
+                Flow::Stop => return Flow::Stop,
+                Flow::Rollback => return Flow::Rollback,
+                Flow::Continue | Flow::NextIteration => {}
             }
         }
     }
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::runtime::value::Value;
+
+/// A function callable from expressions as `name(arg, ...)`; an `Err` is reported as a
+/// math error naming the call
+pub type NativeFunction = Arc<dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync>;
+
+/// Functions available to expressions, keyed by name
+#[derive(Clone)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, NativeFunction>,
+}
+
+impl FunctionRegistry {
+    /// A registry with no functions at all
+    pub fn empty() -> Self {
+        FunctionRegistry { functions: HashMap::new() }
+    }
+
+    /// A registry holding the built-ins: min, max, abs, clamp, len, round, upper, lower, now
+    pub fn with_builtins() -> Self {
+        let mut registry = FunctionRegistry::empty();
+        registry.register("min", |args| extreme(args, std::cmp::Ordering::Less));
+        registry.register("max", |args| extreme(args, std::cmp::Ordering::Greater));
+        registry.register("abs", abs);
+        registry.register("clamp", clamp);
+        registry.register("len", len);
+        registry.register("round", round);
+        registry.register("upper", |args| text(args).map(|s| Value::Str(s.to_uppercase())));
+        registry.register("lower", |args| text(args).map(|s| Value::Str(s.to_lowercase())));
+        registry.register("now", now);
+        registry
+    }
+
+    /// Adds or replaces a function
+    pub fn register<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.functions.insert(name.to_string(), Arc::new(function));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NativeFunction> {
+        self.functions.get(name)
+    }
+
+    /// Registered names, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.functions.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl Default for FunctionRegistry {
+    fn default() -> Self {
+        FunctionRegistry::with_builtins()
+    }
+}
+
+impl fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+fn arity(args: &[Value], expected: usize) -> Result<(), String> {
+    if args.len() == expected {
+        Ok(())
+    } else {
+        Err(format!("expects {} argument{}, got {}", expected, if expected == 1 { "" } else { "s" }, args.len()))
+    }
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Int(n) => Ok(*n as f64),
+        Value::BigInt(n) => Ok(*n as f64),
+        Value::Float(x) => Ok(*x),
+        other => Err(format!("expects numbers, got {} '{}'", other.type_name(), other)),
+    }
+}
+
+/// Orders two numbers (int or float) or two texts
+fn compare(a: &Value, b: &Value) -> Result<std::cmp::Ordering, String> {
+    match (a, b) {
+        (a, b) if let (Some(a), Some(b)) = (a.as_integer(), b.as_integer()) => Ok(a.cmp(&b)),
+        (Value::Str(a), Value::Str(b)) => Ok(a.cmp(b)),
+        (a, b) => number(a)?
+            .partial_cmp(&number(b)?)
+            .ok_or_else(|| "cannot order NaN".to_string()),
+    }
+}
+
+fn extreme(args: &[Value], wanted: std::cmp::Ordering) -> Result<Value, String> {
+    let (first, rest) = args.split_first().ok_or("expects at least 1 argument, got 0")?;
+    let mut best = first;
+    for candidate in rest {
+        if compare(candidate, best)? == wanted {
+            best = candidate;
+        }
+    }
+    Ok(best.clone())
+}
+
+fn abs(args: &[Value]) -> Result<Value, String> {
+    arity(args, 1)?;
+    match &args[0] {
+        value if let Some(n) = value.as_integer() => {
+            n.checked_abs().map(Value::integer).ok_or_else(|| "integer overflow".to_string())
+        }
+        other => Ok(Value::Float(number(other)?.abs())),
+    }
+}
+
+fn clamp(args: &[Value]) -> Result<Value, String> {
+    arity(args, 3)?;
+    let (value, low, high) = (&args[0], &args[1], &args[2]);
+    if compare(low, high)?.is_gt() {
+        return Err(format!("lower bound {} is above upper bound {}", low, high));
+    }
+    if compare(value, low)?.is_lt() {
+        Ok(low.clone())
+    } else if compare(value, high)?.is_gt() {
+        Ok(high.clone())
+    } else {
+        Ok(value.clone())
+    }
+}
+
+fn len(args: &[Value]) -> Result<Value, String> {
+    arity(args, 1)?;
+    match &args[0] {
+        Value::Str(text) => Ok(Value::Int(text.chars().count() as i64)),
+        Value::List(items) => Ok(Value::Int(items.len() as i64)),
+        Value::Map(entries) => Ok(Value::Int(entries.len() as i64)),
+        other => Err(format!("{} '{}' has no length", other.type_name(), other)),
+    }
+}
+
+/// `round(x)` gives an int; `round(x, places)` keeps a float with that many decimals
+fn round(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [x] if x.as_integer().is_some() => Ok(x.clone()),
+        [x] => {
+            let rounded = number(x)?.round();
+            // `i64::MAX as f64` is 2^63, one past the largest i64
+            if rounded.is_finite() && rounded >= i64::MIN as f64 && rounded < i64::MAX as f64 {
+                Ok(Value::Int(rounded as i64))
+            } else {
+                Err(format!("{} does not fit in an integer", x))
+            }
+        }
+        [x, Value::Int(places)] if (0..=15).contains(places) => {
+            let factor = 10f64.powi(*places as i32);
+            Ok(Value::Float((number(x)? * factor).round() / factor))
+        }
+        [_, places] => Err(format!("expects 0 to 15 decimal places, got '{}'", places)),
+        _ => Err(format!("expects 1 or 2 arguments, got {}", args.len())),
+    }
+}
+
+fn text(args: &[Value]) -> Result<&str, String> {
+    arity(args, 1)?;
+    match &args[0] {
+        Value::Str(text) => Ok(text),
+        other => Err(format!("expects text, got {} '{}'", other.type_name(), other)),
+    }
+}
+
+/// Seconds since the Unix epoch
+fn now(args: &[Value]) -> Result<Value, String> {
+    arity(args, 0)?;
+    let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?;
+    Ok(Value::Int(elapsed.as_secs() as i64))
+}
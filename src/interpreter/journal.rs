@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+use crate::parser::span::Span;
+use crate::parser::stmt::Query;
+use crate::runtime::memory::{MemoryStore, MemoryWrite};
+
+/// Entries kept before the oldest are dropped, so long-running loops stay bounded
+pub const JOURNAL_LIMIT: usize = 4096;
+
+/// A condition that was evaluated on the way to a statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guard {
+    /// Source-like header, e.g. `if "({{count}} > 3)"`
+    pub header: String,
+    /// What the condition evaluated to
+    pub value: String,
+    /// Whether the guarded branch ran
+    pub held: bool,
+    pub span: Span,
+}
+
+/// Why a statement did not run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipCause {
+    /// The enclosing condition chose the other branch
+    Condition(Guard),
+    /// The enclosing condition could not be evaluated
+    ConditionFailed { header: String, error: String, span: Span },
+    /// `stop`, `break` or `continue` unwound the block before reaching it
+    Unwound { keyword: String, span: Span },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Executed,
+    Skipped(SkipCause),
+}
+
+/// One statement the runtime ran or passed over
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub span: Span,
+    /// Source-like rendering of the statement header
+    pub statement: String,
+    pub outcome: Outcome,
+    /// Memory scopes active at the time, e.g. `global > event:start`
+    pub scope_chain: String,
+    /// Conditions that had to hold for this statement to be reached
+    pub guards: Vec<Guard>,
+    /// What `respond` / `respond_event` produced
+    pub response: Option<String>,
+    pub tick: u32,
+}
+
+/// Structured history of execution that `why:` and `explain:` queries are answered from
+#[derive(Debug, Default)]
+pub struct Journal {
+    entries: VecDeque<JournalEntry>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal { entries: VecDeque::new() }
+    }
+
+    /// Appends an entry and returns its index for later updates
+    pub fn record(&mut self, entry: JournalEntry) -> usize {
+        if self.entries.len() >= JOURNAL_LIMIT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        self.entries.len() - 1
+    }
+
+    pub fn set_response(&mut self, index: usize, response: &str) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.response = Some(response.to_string());
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &JournalEntry> {
+        self.entries.iter()
+    }
+
+    /// Answers a query from the most recent matching entries and the memory write log;
+    /// `source` names the script in locations the same way runtime messages do
+    pub fn answer(&self, query: &Query, memory: &MemoryStore, source: Option<&str>) -> String {
+        match query {
+            Query::Skipped(target) => {
+                let Some(entry) = self.latest(|e| matches_text(&e.statement, target)) else {
+                    return format!("No statement matching \"{}\" has run or been skipped.", target);
+                };
+                let at = entry.span.describe(source);
+                match &entry.outcome {
+                    Outcome::Skipped(cause) => {
+                        format!("Skipped `{}` at {} because {}.", entry.statement, at, describe_cause(cause, source))
+                    }
+                    Outcome::Executed => format!(
+                        "It did not skip `{}`: it ran at {} in [{}]{}.",
+                        entry.statement,
+                        at,
+                        entry.scope_chain,
+                        describe_guards(&entry.guards, source)
+                    ),
+                }
+            }
+            Query::Responded(target) => {
+                let responded = self.latest(|e| {
+                    e.response.as_deref().is_some_and(|r| matches_text(r, target))
+                });
+                if let Some(entry) = responded {
+                    return explain_response(entry, source);
+                }
+                let skipped = self.entries.iter().rev().find_map(|e| match &e.outcome {
+                    Outcome::Skipped(cause) if matches_text(&e.statement, target) => Some((e, cause)),
+                    _ => None,
+                });
+                match skipped {
+                    Some((entry, cause)) => format!(
+                        "It did not respond \"{}\": `{}` at {} was skipped because {}.",
+                        target,
+                        entry.statement,
+                        entry.span.describe(source),
+                        describe_cause(cause, source)
+                    ),
+                    None => format!("Nothing has responded with \"{}\".", target),
+                }
+            }
+            Query::LastResponse => match self.latest(|e| e.response.is_some()) {
+                Some(entry) => explain_response(entry, source),
+                None => "Nothing has responded yet.".to_string(),
+            },
+            Query::TraceAfter(target) => self.trace_after(target, source),
+            Query::TraceKey(key) => trace_key(key, memory, source),
+            Query::Trace(target) => match memory.writes_to(target).next() {
+                Some(_) => trace_key(target, memory, source),
+                None => self.trace_after(target, source),
+            },
+        }
+    }
+
+    /// Lists, in order, every statement that ran after the latest run of `target`
+    fn trace_after(&self, target: &str, source: Option<&str>) -> String {
+        let start = self
+            .entries
+            .iter()
+            .rposition(|e| e.outcome == Outcome::Executed && matches_text(&e.statement, target));
+        let Some(start) = start else {
+            return format!("No statement matching \"{}\" has run.", target);
+        };
+
+        let anchor = &self.entries[start];
+        let mut lines = vec![format!("After `{}` at {}:", anchor.statement, anchor.span.describe(source))];
+        let executed = self.entries.iter().skip(start + 1).filter(|e| e.outcome == Outcome::Executed);
+        for (n, entry) in executed.enumerate() {
+            lines.push(format!(
+                "  {}. `{}` at {} in [{}]",
+                n + 1,
+                entry.statement,
+                entry.span.describe(source),
+                entry.scope_chain
+            ));
+        }
+        if lines.len() == 1 {
+            lines.push("  nothing else ran".to_string());
+        }
+        lines.join("\n")
+    }
+
+    fn latest(&self, predicate: impl Fn(&JournalEntry) -> bool) -> Option<&JournalEntry> {
+        self.entries.iter().rev().find(|e| predicate(e))
+    }
+}
+
+/// Lists every recorded write to a memory key, oldest first
+fn trace_key(key: &str, memory: &MemoryStore, source: Option<&str>) -> String {
+    let writes: Vec<&MemoryWrite> = memory.writes_to(key).collect();
+    if writes.is_empty() {
+        return format!("Nothing has written \"{}\".", key);
+    }
+
+    let mut lines = vec![format!("Writes to \"{}\":", key)];
+    for (n, write) in writes.iter().enumerate() {
+        let origin = match (&write.via, write.span) {
+            (Some(via), Some(span)) => format!("{} by {}", via, span.describe(source)),
+            (Some(via), None) => via.clone(),
+            (None, Some(span)) => format!("from {}", span.describe(source)),
+            (None, None) => "from the host".to_string(),
+        };
+        lines.push(format!(
+            "  {}. {} = {} in [{}] at tick {} {}",
+            n + 1,
+            write.key,
+            write.value,
+            write.scope,
+            write.tick,
+            origin
+        ));
+    }
+    lines.join("\n")
+}
+
+fn explain_response(entry: &JournalEntry, source: Option<&str>) -> String {
+    format!(
+        "Responded {} from `{}` at {} in [{}]{}.",
+        entry.response.as_deref().unwrap_or_default(),
+        entry.statement,
+        entry.span.describe(source),
+        entry.scope_chain,
+        describe_guards(&entry.guards, source)
+    )
+}
+
+fn describe_cause(cause: &SkipCause, source: Option<&str>) -> String {
+    match cause {
+        SkipCause::Condition(guard) => format!(
+            "`{}` at {} evaluated to {}",
+            guard.header,
+            guard.span.describe(source),
+            guard.value
+        ),
+        SkipCause::ConditionFailed { header, error, span } => {
+            format!("`{}` at {} could not be evaluated: {}", header, span.describe(source), error)
+        }
+        SkipCause::Unwound { keyword, span } => {
+            format!("`{}` at {} left the block first", keyword, span.describe(source))
+        }
+    }
+}
+
+fn describe_guards(guards: &[Guard], source: Option<&str>) -> String {
+    if guards.is_empty() {
+        return String::new();
+    }
+    let reasons: Vec<String> = guards
+        .iter()
+        .map(|g| match g.held {
+            true => format!("`{}` at {} evaluated to {}", g.header, g.span.describe(source), g.value),
+            false => format!(
+                "`{}` at {} evaluated to {} so its `else` ran",
+                g.header,
+                g.span.describe(source),
+                g.value
+            ),
+        })
+        .collect();
+    format!(" because {}", reasons.join(" and "))
+}
+
+/// Loose comparison so `respond Welcome` finds `respond "Welcome"`
+fn matches_text(haystack: &str, needle: &str) -> bool {
+    fn normalize(text: &str) -> String {
+        text.replace('"', "")
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ")
+            .to_lowercase()
+    }
+    normalize(haystack).contains(&normalize(needle))
+}
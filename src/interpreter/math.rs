@@ -1,246 +1,381 @@
 use std::collections::HashMap;
+use crate::interpreter::functions::FunctionRegistry;
+use crate::lang::expr::{parse, Expr, Form, Part};
+pub use crate::lang::expr::{is_expression, CompiledExpr, Template};
+pub use crate::lang::policy::OverflowPolicy;
+use crate::runtime::path::{self, ParsedKey};
+use crate::runtime::value::Value;
+use crate::utils::utils::nearest_key;
 
-const DEBUG_PRINT: bool = false;
+/// Runtime settings that change how expressions evaluate
+#[derive(Debug, Clone, Default)]
+pub struct EvalOptions {
+    pub overflow: OverflowPolicy,
+    pub functions: FunctionRegistry,
+    /// Unresolved placeholders fail instead of reading as 0
+    pub strict: bool,
+}
 
-/// Public: Evaluates a math or logical expression with memory substitution.
-pub fn evaluate_expression(expression: &str, memory: &HashMap<String, String>) -> Result<i32, String> {
-    let cleaned = strip_quotes(expression);
-    let resolved = resolve_placeholders(&cleaned, memory);
-    let stripped = resolved.trim_matches('"').to_string();
+/// A successful evaluation, with anything the runtime should warn about
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub value: Value,
+    pub warnings: Vec<String>,
+    /// Placeholders that could not be resolved and were read as 0 (lenient mode only)
+    pub substitutions: Vec<String>,
+}
+
+/// Text with its placeholders filled in, plus the same notes as an `Evaluation`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rendered {
+    pub text: String,
+    pub warnings: Vec<String>,
+    pub substitutions: Vec<String>,
+}
+
+/// Read access to memory for evaluation; a key may be a path such as `user.roles[0]`
+pub trait Lookup {
+    fn lookup(&self, key: &str) -> Option<Value>;
 
-    if DEBUG_PRINT {
-        println!("Resolved expression: [{}]", stripped);
+    /// `lookup` for a key compiled into an expression, whose path is already parsed
+    fn lookup_parsed(&self, key: &ParsedKey) -> Option<Value> {
+        self.lookup(&key.name)
     }
 
-    let tokens = tokenize(&stripped)?;
-    if DEBUG_PRINT {
-        println!("Tokens: {:?}", tokens);
+    /// Every visible key, used to suggest the nearest one when a lookup misses
+    fn keys(&self) -> Vec<String>;
+}
+
+impl Lookup for HashMap<String, Value> {
+    fn lookup(&self, key: &str) -> Option<Value> {
+        path::lookup(self, key)
     }
 
-    let rpn = shunting_yard(&tokens)?;
-    if DEBUG_PRINT {
-        println!("RPN Output: {:?}", rpn);
+    fn lookup_parsed(&self, key: &ParsedKey) -> Option<Value> {
+        path::lookup_in(self, &key.name, key.path.as_ref())
     }
 
-    evaluate_rpn(&rpn)
+    fn keys(&self) -> Vec<String> {
+        self.keys().cloned().collect()
+    }
 }
 
-/// Public: Replaces all `{{math}}` blocks in a string with evaluated results.
-pub fn resolve_math_placeholders(input: &str, memory: &HashMap<String, String>) -> String {
-    let mut output = String::new();
-    let mut chars = input.chars().peekable();
-
-    while let Some(c) = chars.next() {
-        if c == '{' && chars.peek() == Some(&'{') {
-            chars.next(); // skip second '{'
-            let mut expr = String::new();
-
-            while let Some(&next) = chars.peek() {
-                if next == '}' {
-                    chars.next();
-                    if chars.peek() == Some(&'}') {
-                        chars.next();
-                        break;
-                    } else {
-                        expr.push('}');
-                    }
-                } else {
-                    expr.push(next);
-                    chars.next();
-                }
-            }
+/// State threaded through one evaluation
+struct Context<'a> {
+    memory: &'a dyn Lookup,
+    options: &'a EvalOptions,
+    warnings: Vec<String>,
+    substitutions: Vec<String>,
+}
 
-            match evaluate_expression(&expr, memory) {
-                Ok(result) => output.push_str(&result.to_string()),
-                Err(_) => output.push_str("0"),
-            }
-        } else {
-            output.push(c);
-        }
+impl<'a> Context<'a> {
+    fn new(memory: &'a dyn Lookup, options: &'a EvalOptions) -> Self {
+        Context { memory, options, warnings: Vec::new(), substitutions: Vec::new() }
     }
 
-    output
+    /// A placeholder names a key memory does not hold: an error in strict mode, otherwise 0
+    fn unresolved(&mut self, key: &str) -> Result<Value, String> {
+        let keys = self.memory.keys();
+        let hint = match nearest_key(key, keys.iter().map(String::as_str)) {
+            Some(nearest) => format!("; nearest key is '{}'", nearest),
+            None => "; memory holds no keys".to_string(),
+        };
+        if self.options.strict {
+            return Err(format!("Math error: unresolved placeholder '{{{{{}}}}}'{}", key, hint));
+        }
+        self.substitutions.push(format!("'{{{{{}}}}}' is unresolved{}; substituted 0", key, hint));
+        Ok(Value::Int(0))
+    }
 }
 
-fn strip_quotes(input: &str) -> &str {
-    if input.starts_with('"') && input.ends_with('"') && input.len() >= 2 {
-        &input[1..input.len() - 1]
-    } else {
-        input
-    }
+/// Public: Evaluates a math or logical expression with memory substitution.
+pub fn evaluate_expression(expression: &str, memory: &dyn Lookup) -> Result<Value, String> {
+    evaluate_with(expression, memory, &EvalOptions::default()).map(|e| e.value)
 }
 
-fn resolve_placeholders(input: &str, memory: &HashMap<String, String>) -> String {
-    let mut output = String::new();
-    let mut chars = input.chars().peekable();
+/// Evaluates an expression under the given options, returning warnings alongside the value
+pub fn evaluate_with(expression: &str, memory: &dyn Lookup, options: &EvalOptions) -> Result<Evaluation, String> {
+    run(&parse(expression)?, memory, options)
+}
 
-    while let Some(c) = chars.next() {
-        if c == '{' && chars.peek() == Some(&'{') {
-            chars.next();
-            let mut key = String::new();
+fn run(expr: &Expr, memory: &dyn Lookup, options: &EvalOptions) -> Result<Evaluation, String> {
+    let mut cx = Context::new(memory, options);
+    let value = evaluate(expr, &mut cx)?;
+    Ok(Evaluation { value, warnings: cx.warnings, substitutions: cx.substitutions })
+}
 
-            while let Some(&next) = chars.peek() {
-                if next == '}' {
-                    chars.next();
-                    if chars.peek() == Some(&'}') {
-                        chars.next();
-                        break;
-                    } else {
-                        key.push('}');
-                    }
-                } else {
-                    key.push(next);
-                    chars.next();
-                }
+impl CompiledExpr {
+    pub fn evaluate(&self, memory: &dyn Lookup, options: &EvalOptions) -> Result<Evaluation, String> {
+        let result = match &self.form {
+            Form::Literal(value) => {
+                return Ok(Evaluation { value: value.clone(), warnings: Vec::new(), substitutions: Vec::new() });
             }
-
-            let resolved = memory.get(key.trim()).cloned().unwrap_or_else(|| "0".to_string());
-            output.push_str(&resolved);
-        } else {
-            output.push(c);
-        }
+            Form::Expr(expr) => run(expr, memory, options),
+            Form::Invalid(e) => Err(e.clone()),
+        };
+        result.map_err(|e| format!("Failed to evaluate expression '{}': {}", self.source, e))
     }
-
-    output
 }
 
-fn tokenize(expr: &str) -> Result<Vec<String>, String> {
-    let mut tokens = Vec::new();
-    let mut chars = expr.chars().peekable();
+impl Template {
+    /// Fills in every placeholder. One that names a missing key or fails to evaluate is an
+    /// error in strict mode and renders as `0` otherwise
+    pub fn render(&self, memory: &dyn Lookup, options: &EvalOptions) -> Result<Rendered, String> {
+        let mut cx = Context::new(memory, options);
+        let mut text = String::new();
 
-    while let Some(&c) = chars.peek() {
-        match c {
-            '0'..='9' => {
-                let mut num = String::new();
-                while let Some(&d) = chars.peek() {
-                    if d.is_ascii_digit() {
-                        num.push(d);
-                        chars.next();
-                    } else {
-                        break;
-                    }
+        for part in &self.parts {
+            let (key, expr) = match part {
+                Part::Text(run) => {
+                    text.push_str(run);
+                    continue;
                 }
-                tokens.push(num);
-            }
-            '+' | '-' | '*' | '/' | '%' | '(' | ')' => {
-                tokens.push(c.to_string());
-                chars.next();
+                Part::Slot { key, expr } => (key, expr),
+            };
+            if let Some(value) = memory.lookup_parsed(key) {
+                text.push_str(&value.to_string());
+                continue;
             }
-            '=' | '!' | '<' | '>' => {
-                let mut op = c.to_string();
-                chars.next();
-                if chars.peek() == Some(&'=') {
-                    op.push('=');
-                    chars.next();
+            let result = match expr {
+                // A lone key that memory does not hold
+                Ok(Expr::Word(_) | Expr::Var(_)) => {
+                    text.push_str(&cx.unresolved(&key.name)?.to_string());
+                    continue;
+                }
+                Ok(expr) => evaluate(expr, &mut cx),
+                Err(e) => Err(e.clone()),
+            };
+            match result {
+                Ok(value) => text.push_str(&value.to_string()),
+                Err(e) if options.strict => return Err(format!("placeholder '{{{{{}}}}}' failed: {}", key.name, e)),
+                Err(e) => {
+                    cx.substitutions.push(format!("'{{{{{}}}}}' failed ({}); substituted 0", key.name, e));
+                    text.push('0');
                 }
-                tokens.push(op);
-            }
-            ' ' => {
-                chars.next();
             }
-            _ => return Err(format!("Math error: unexpected character '{}'", c)),
         }
+
+        Ok(Rendered { text, warnings: cx.warnings, substitutions: cx.substitutions })
     }
+}
 
-    Ok(tokens)
+/// Public: Replaces all `{{math}}` blocks in a string with evaluated results.
+pub fn resolve_math_placeholders(input: &str, memory: &dyn Lookup) -> String {
+    match resolve_placeholders_with(input, memory, &EvalOptions::default()) {
+        Ok(rendered) => rendered.text,
+        Err(_) => input.to_string(),
+    }
 }
 
-fn shunting_yard(tokens: &[String]) -> Result<Vec<String>, String> {
-    let mut output = Vec::new();
-    let mut ops: Vec<String> = Vec::new();
+/// Replaces `{{math}}` blocks under the given options, returning warnings alongside the text
+pub fn resolve_placeholders_with(input: &str, memory: &dyn Lookup, options: &EvalOptions) -> Result<Rendered, String> {
+    Template::new(input).render(memory, options)
+}
 
-    for token in tokens {
-        if token.parse::<i32>().is_ok() {
-            output.push(token.clone());
-        } else if is_operator(token) {
-            while let Some(top) = ops.last() {
-                if is_operator(top) && precedence(top) >= precedence(token) {
-                    output.push(ops.pop().unwrap());
-                } else {
-                    break;
+fn evaluate(expr: &Expr, cx: &mut Context) -> Result<Value, String> {
+    let memory = cx.memory;
+    match expr {
+        Expr::Literal(value) => Ok(value.clone()),
+        // Missing keys read as 0 outside strict mode so arithmetic on not-yet-set counters keeps working
+        Expr::Var(key) => match memory.lookup_parsed(key) {
+            Some(value) => Ok(value),
+            None => cx.unresolved(&key.name),
+        },
+        Expr::Word(word) => Ok(memory.lookup_parsed(word).unwrap_or_else(|| Value::Str(word.name.clone()))),
+        Expr::Neg(operand) => match evaluate(operand, cx)? {
+            Value::Float(x) => Ok(Value::Float(-x)),
+            value if let Some(n) = value.as_integer() => {
+                let exact = n.checked_neg();
+                match exact.map(i64::try_from) {
+                    Some(Ok(negated)) => Ok(Value::Int(negated)),
+                    _ => on_overflow(cx, &format!("-{}", n), exact, n.wrapping_neg(), n > 0),
                 }
             }
-            ops.push(token.clone());
-        } else if token == "(" {
-            ops.push(token.clone());
-        } else if token == ")" {
-            let mut matched = false;
-            while let Some(op) = ops.pop() {
-                if op == "(" {
-                    matched = true;
-                    break;
-                }
-                output.push(op);
-            }
-            if !matched {
-                return Err("Math error: unmatched ')'".to_string());
-            }
-        } else {
-            return Err(format!("Math error: unknown token '{}'", token));
+            other => Err(format!("Math error: cannot negate {} '{}'", other.type_name(), other)),
+        },
+        Expr::Not(operand) => Ok(Value::Bool(!evaluate(operand, cx)?.is_truthy())),
+        Expr::And(lhs, rhs) => Ok(Value::Bool(
+            evaluate(lhs, cx)?.is_truthy() && evaluate(rhs, cx)?.is_truthy(),
+        )),
+        Expr::Or(lhs, rhs) => Ok(Value::Bool(
+            evaluate(lhs, cx)?.is_truthy() || evaluate(rhs, cx)?.is_truthy(),
+        )),
+        Expr::Missing { operand, negated } => {
+            let key = match operand.as_ref() {
+                Expr::Var(key) | Expr::Word(key) => key,
+                _ => return Err("Math error: only a memory key can be missing".to_string()),
+            };
+            Ok(Value::Bool(memory.lookup_parsed(key).is_none() != *negated))
+        }
+        Expr::Has(container, item) => {
+            let container = evaluate(container, cx)?;
+            // `user has id` names a key, so a bare word on the right is taken literally
+            let item = match item.as_ref() {
+                Expr::Word(word) if matches!(container, Value::Map(_)) => Value::Str(word.name.clone()),
+                other => evaluate(other, cx)?,
+            };
+            contains(&container, &item).map(Value::Bool)
+        }
+        Expr::Length(operand) => match evaluate(operand, cx)? {
+            Value::Str(text) => Ok(Value::Int(text.chars().count() as i64)),
+            Value::List(items) => Ok(Value::Int(items.len() as i64)),
+            Value::Map(entries) => Ok(Value::Int(entries.len() as i64)),
+            other => Err(format!("Math error: {} '{}' has no length", other.type_name(), other)),
+        },
+        Expr::Call { name, args, position } => {
+            let values = args.iter().map(|arg| evaluate(arg, cx)).collect::<Result<Vec<_>, _>>()?;
+            let function = cx.options.functions.get(name).ok_or_else(|| {
+                format!("Math error: unknown function '{}' at position {}", name, position)
+            })?;
+            function(&values).map_err(|e| format!("Math error: {}() {}", name, e))
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let (a, b) = (evaluate(lhs, cx)?, evaluate(rhs, cx)?);
+            apply_operator(op, a, b, cx)
         }
     }
+}
 
-    while let Some(op) = ops.pop() {
-        if op == "(" || op == ")" {
-            return Err("Math error: mismatched parentheses".to_string());
-        }
-        output.push(op);
+fn contains(container: &Value, item: &Value) -> Result<bool, String> {
+    match (container, item) {
+        (Value::Map(entries), Value::Str(key)) => Ok(entries.contains_key(key)),
+        (Value::List(items), item) => Ok(items.iter().any(|i| values_equal(i, item))),
+        (Value::Str(text), Value::Str(part)) => Ok(text.contains(part.as_str())),
+        (container, item) => Err(format!(
+            "Math error: cannot check whether {} '{}' has {} '{}'",
+            container.type_name(),
+            container,
+            item.type_name(),
+            item
+        )),
     }
+}
 
-    Ok(output)
+/// Two ints stay ints (`/` truncates toward zero); an int meeting a float is promoted.
+/// `+` with text on either side concatenates, and text compares lexicographically
+fn apply_operator(op: &str, a: Value, b: Value, cx: &mut Context) -> Result<Value, String> {
+    match op {
+        "==" => return Ok(Value::Bool(values_equal(&a, &b))),
+        "!=" => return Ok(Value::Bool(!values_equal(&a, &b))),
+        _ => {}
+    }
+
+    match (a, b) {
+        (Value::Str(a), Value::Str(b)) if op != "+" => match op {
+            "<" => Ok(Value::Bool(a < b)),
+            "<=" => Ok(Value::Bool(a <= b)),
+            ">" => Ok(Value::Bool(a > b)),
+            ">=" => Ok(Value::Bool(a >= b)),
+            "starts with" => Ok(Value::Bool(a.starts_with(&b))),
+            "ends with" => Ok(Value::Bool(a.ends_with(&b))),
+            _ => Err(format!("Math error: cannot apply '{}' to text '{}' and text '{}'", op, a, b)),
+        },
+        (a, b) if op == "+" && (matches!(a, Value::Str(_)) || matches!(b, Value::Str(_))) => {
+            Ok(Value::Str(format!("{}{}", a, b)))
+        }
+        (a, b) if let (Some(a), Some(b)) = (a.as_integer(), b.as_integer()) => match op {
+            "/" if b == 0 => Err("Math error: division by zero".into()),
+            "%" if b == 0 => Err("Math error: modulo by zero".into()),
+            "+" | "-" | "*" | "/" | "%" => int_arithmetic(op, a, b, cx),
+            "<" => Ok(Value::Bool(a < b)),
+            "<=" => Ok(Value::Bool(a <= b)),
+            ">" => Ok(Value::Bool(a > b)),
+            ">=" => Ok(Value::Bool(a >= b)),
+            _ => Err(format!("Math error: unknown operator '{}'", op)),
+        },
+        (a, b) => match (as_float(&a), as_float(&b)) {
+            (Some(x), Some(y)) => match op {
+                "+" => Ok(Value::Float(x + y)),
+                "-" => Ok(Value::Float(x - y)),
+                "*" => Ok(Value::Float(x * y)),
+                "/" if y == 0.0 => Err("Math error: division by zero".into()),
+                "/" => Ok(Value::Float(x / y)),
+                "%" if y == 0.0 => Err("Math error: modulo by zero".into()),
+                "%" => Ok(Value::Float(x % y)),
+                "<" => Ok(Value::Bool(x < y)),
+                "<=" => Ok(Value::Bool(x <= y)),
+                ">" => Ok(Value::Bool(x > y)),
+                ">=" => Ok(Value::Bool(x >= y)),
+                _ => Err(format!("Math error: unknown operator '{}'", op)),
+            },
+            _ => Err(format!(
+                "Math error: cannot apply '{}' to {} '{}' and {} '{}'",
+                op,
+                a.type_name(),
+                a,
+                b.type_name(),
+                b
+            )),
+        },
+    }
 }
 
-fn evaluate_rpn(tokens: &[String]) -> Result<i32, String> {
-    let mut stack: Vec<i32> = Vec::new();
+/// Integer arithmetic is done in `i128`, which holds any result of two `i64` operands
+/// exactly; the overflow policy decides what happens to a result outside the `i64` range
+fn int_arithmetic(op: &str, a: i128, b: i128, cx: &mut Context) -> Result<Value, String> {
+    let (exact, wrapped) = match op {
+        "+" => (a.checked_add(b), a.wrapping_add(b)),
+        "-" => (a.checked_sub(b), a.wrapping_sub(b)),
+        "*" => (a.checked_mul(b), a.wrapping_mul(b)),
+        "/" => (a.checked_div(b), a.wrapping_div(b)),
+        // Only `MIN % -1` fails to check, and its exact result is 0
+        _ => (Some(a.wrapping_rem(b)), a.wrapping_rem(b)),
+    };
+    if let Some(n) = exact
+        && let Ok(n) = i64::try_from(n)
+    {
+        return Ok(Value::Int(n));
+    }
 
-    for token in tokens {
-        if let Ok(n) = token.parse::<i32>() {
-            stack.push(n);
-        } else {
-            let b = stack.pop().ok_or_else(|| "Math error: missing right operand".to_string())?;
-            let a = stack.pop().ok_or_else(|| "Math error: missing left operand".to_string())?;
+    let negative = match op {
+        "*" | "/" => (a < 0) != (b < 0),
+        _ => a < 0,
+    };
+    on_overflow(cx, &format!("{} {} {}", a, op, b), exact, wrapped, negative)
+}
 
-            let result = match token.as_str() {
-                "+" => a + b,
-                "-" => a - b,
-                "*" => a * b,
-                "/" => {
-                    if b == 0 {
-                        return Err("Math error: division by zero".into());
-                    }
-                    a / b
-                }
-                "%" => {
-                    if b == 0 {
-                        return Err("Math error: modulo by zero".into());
-                    }
-                    a % b
-                }
-                "==" => (a == b) as i32,
-                "!=" => (a != b) as i32,
-                "<" => (a < b) as i32,
-                "<=" => (a <= b) as i32,
-                ">" => (a > b) as i32,
-                ">=" => (a >= b) as i32,
-                _ => return Err(format!("Math error: unknown operator '{}'", token)),
+/// Applies the overflow policy to a result outside the `i64` range. `exact` is `None` when
+/// even 128 bits could not hold it, in which case `negative` gives its sign
+fn on_overflow(cx: &mut Context, operation: &str, exact: Option<i128>, wrapped: i128, negative: bool) -> Result<Value, String> {
+    let (value, action) = match cx.options.overflow {
+        OverflowPolicy::Error => {
+            return Err(format!("Math error: integer overflow in '{}'", operation));
+        }
+        OverflowPolicy::Saturate => {
+            let saturated = match exact {
+                Some(n) => n.clamp(i64::MIN as i128, i64::MAX as i128) as i64,
+                None if negative => i64::MIN,
+                None => i64::MAX,
             };
-
-            stack.push(result);
+            (Value::Int(saturated), "saturated")
         }
-    }
-
-    stack.pop().ok_or_else(|| "Math error: expression produced no result".to_string())
+        // Truncating the 128-bit result is the same as wrapping in 64 bits
+        OverflowPolicy::Wrap => (Value::Int(wrapped as i64), "wrapped"),
+        OverflowPolicy::Promote => match exact {
+            Some(n) => (Value::BigInt(n), "promoted"),
+            None => return Err(format!("Math error: integer overflow in '{}' exceeds 128 bits", operation)),
+        },
+    };
+    cx.warnings.push(format!("integer overflow in '{}' {} to {}", operation, action, value));
+    Ok(value)
 }
 
-fn is_operator(op: &str) -> bool {
-    matches!(op, "+" | "-" | "*" | "/" | "%" | "==" | "!=" | "<" | "<=" | ">" | ">=")
+fn as_float(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(n) => Some(*n as f64),
+        Value::BigInt(n) => Some(*n as f64),
+        Value::Float(x) => Some(*x),
+        _ => None,
+    }
 }
 
-fn precedence(op: &str) -> i32 {
-    match op {
-        "==" | "!=" | "<" | "<=" | ">" | ">=" => 0,
-        "+" | "-" => 1,
-        "*" | "/" | "%" => 2,
-        _ => -1,
+/// Numbers compare by value across int and float; everything else compares structurally
+fn values_equal(a: &Value, b: &Value) -> bool {
+    if let (Some(x), Some(y)) = (a.as_integer(), b.as_integer()) {
+        return x == y;
+    }
+    match (as_float(a), as_float(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
     }
 }
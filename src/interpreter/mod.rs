@@ -1,4 +1,6 @@
 pub mod math;
 pub mod engine; // now active and usable
+pub mod journal;
+pub mod functions;
 // pub mod control;        // (planned) branching / execution flow
 // pub mod events;         // (planned) async triggers, on/while/event logic
@@ -0,0 +1,543 @@
+use std::fmt;
+use crate::runtime::path::ParsedKey;
+use crate::runtime::value::Value;
+
+const DEBUG_PRINT: bool = false;
+
+/// A lexical unit of an expression, with the 1-based character position it starts at
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(Value),
+    /// `{{key}}` placeholder
+    Var(String),
+    /// Bare word: a memory key, an English operator, or plain text
+    Word(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/// Parsed expression; `and` / `or` only evaluate their right side when needed
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Literal(Value),
+    /// Placeholder; a missing key reads as 0
+    Var(ParsedKey),
+    /// Bare word; a missing key reads as the word itself
+    Word(ParsedKey),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Has(Box<Expr>, Box<Expr>),
+    /// `length x` / `length of x`: characters in text, items in a list or map
+    Length(Box<Expr>),
+    /// `name(arg, ...)`, resolved against the function registry when evaluated
+    Call { name: String, args: Vec<Expr>, position: usize },
+    /// `x is missing` / `x is not missing`
+    Missing { operand: Box<Expr>, negated: bool },
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// Whether a statement argument should be evaluated rather than read as a literal: it holds
+/// math symbols, comparisons, calls, placeholders or word operators (`and`, `is`, ...)
+pub fn is_expression(input: &str) -> bool {
+    const SYMBOLS: &[char] = &['+', '-', '*', '/', '%', '(', ')', '=', '<', '>'];
+    input.contains(SYMBOLS)
+        || input.contains("{{")
+        || input.contains("&&")
+        || input.contains("||")
+        || (input.split_whitespace().count() > 1
+            && input.split_whitespace().any(|word| {
+                matches!(word, "and" | "or" | "not" | "is" | "has" | "contains" | "starts" | "ends" | "length")
+            }))
+}
+
+/// A statement argument parsed once at load time: either a literal value or an expression
+/// tree. A parse error is kept and reported each time the argument is evaluated, as it
+/// would be if the text were parsed on the spot
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledExpr {
+    pub(crate) source: String,
+    pub(crate) form: Form,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Form {
+    Literal(Value),
+    Expr(Expr),
+    Invalid(String),
+}
+
+impl CompiledExpr {
+    pub fn new(source: &str) -> Self {
+        let trimmed = strip_quotes(source.trim());
+        let form = if !is_expression(trimmed) {
+            Form::Literal(Value::from_literal(trimmed))
+        } else {
+            match parse(trimmed) {
+                Ok(expr) => Form::Expr(expr),
+                Err(e) => Form::Invalid(e),
+            }
+        };
+        CompiledExpr { source: source.to_string(), form }
+    }
+
+    /// The text as written in the script
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+impl fmt::Display for CompiledExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl PartialEq<str> for CompiledExpr {
+    fn eq(&self, other: &str) -> bool {
+        self.source == other
+    }
+}
+
+/// Text with `{{...}}` placeholders, split once into literal runs and pre-parsed slots
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub(crate) source: String,
+    pub(crate) parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Part {
+    Text(String),
+    /// A placeholder reads its key directly when memory holds it, and is otherwise evaluated
+    Slot { key: ParsedKey, expr: Result<Expr, String> },
+}
+
+impl Template {
+    pub fn new(source: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c == '{' && chars.peek() == Some(&'{') {
+                chars.next(); // skip second '{'
+                let key = read_placeholder(&mut chars);
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                let expr = parse(&key);
+                parts.push(Part::Slot { key: ParsedKey::new(key.trim()), expr });
+            } else {
+                text.push(c);
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Template { source: source.to_string(), parts }
+    }
+
+    /// The text as written in the script
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl PartialEq<str> for Template {
+    fn eq(&self, other: &str) -> bool {
+        self.source == other
+    }
+}
+
+pub(crate) fn parse(expression: &str) -> Result<Expr, String> {
+    let stripped = strip_quotes(expression.trim());
+
+    let tokens = tokenize(stripped)?;
+    if DEBUG_PRINT {
+        println!("Tokens: {:?}", tokens);
+    }
+
+    let expr = Parser { tokens: &tokens, index: 0, end: stripped.chars().count() + 1 }.parse()?;
+    if DEBUG_PRINT {
+        println!("Expression: {:?}", expr);
+    }
+    Ok(expr)
+}
+
+/// Drops the quotes around a script string, but leaves an expression such as
+/// `"a" + "b"`, which only starts and ends with a quote, intact
+pub(crate) fn strip_quotes(input: &str) -> &str {
+    match input.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(inner) if !inner.contains('"') => inner,
+        _ => input,
+    }
+}
+
+/// Reads up to the closing `}}` of a placeholder whose opening braces were already consumed
+fn read_placeholder(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut key = String::new();
+
+    while let Some(&next) = chars.peek() {
+        if next == '}' {
+            chars.next();
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                break;
+            } else {
+                key.push('}');
+            }
+        } else {
+            key.push(next);
+            chars.next();
+        }
+    }
+
+    key
+}
+
+fn tokenize(expr: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+    let mut position = 0;
+
+    while let Some(&c) = chars.peek() {
+        let start = position + 1;
+        let mut take = |chars: &mut std::iter::Peekable<std::str::Chars<'_>>| {
+            position += 1;
+            chars.next()
+        };
+        let token = match c {
+            '0'..='9' => {
+                let mut num = String::new();
+                let mut is_float = false;
+                while let Some(&d) = chars.peek() {
+                    if d.is_ascii_digit() {
+                        num.push(d);
+                        take(&mut chars);
+                    } else if d == '.' && !is_float {
+                        // A decimal point only belongs to the number when a digit follows it
+                        let mut lookahead = chars.clone();
+                        lookahead.next();
+                        if !lookahead.peek().is_some_and(|n| n.is_ascii_digit()) {
+                            break;
+                        }
+                        is_float = true;
+                        num.push(d);
+                        take(&mut chars);
+                    } else {
+                        break;
+                    }
+                }
+                let literal = if is_float {
+                    num.parse::<f64>()
+                        .map(Value::Float)
+                        .map_err(|_| format!("Math error: bad number '{}' at position {}", num, start))?
+                } else {
+                    num.parse::<i128>().map(Value::integer).map_err(|_| {
+                        format!("Math error: number '{}' at position {} is too large", num, start)
+                    })?
+                };
+                Token::Literal(literal)
+            }
+            '{' => {
+                take(&mut chars);
+                if take(&mut chars) != Some('{') {
+                    return Err(format!("Math error: expected '{{{{' to open a placeholder at position {}", start));
+                }
+                let key = read_placeholder(&mut chars);
+                position += key.chars().count() + 2;
+                Token::Var(key.trim().to_string())
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&d) = chars.peek() {
+                    if d.is_alphanumeric() || matches!(d, '_' | '.' | ':' | '[' | ']') {
+                        word.push(d);
+                        take(&mut chars);
+                    } else {
+                        break;
+                    }
+                }
+                match word.as_str() {
+                    "true" => Token::Literal(Value::Bool(true)),
+                    "false" => Token::Literal(Value::Bool(false)),
+                    "null" => Token::Literal(Value::Null),
+                    _ => Token::Word(word),
+                }
+            }
+            '"' | '\'' => {
+                take(&mut chars);
+                let mut text = String::new();
+                loop {
+                    match take(&mut chars) {
+                        Some(q) if q == c => break,
+                        Some('\\') => match take(&mut chars) {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some(escaped) => text.push(escaped),
+                            None => break,
+                        },
+                        Some(ch) => text.push(ch),
+                        None => {
+                            return Err(format!("Math error: unterminated text starting at position {}", start));
+                        }
+                    }
+                }
+                Token::Literal(Value::Str(text))
+            }
+            '(' => {
+                take(&mut chars);
+                Token::LParen
+            }
+            ')' => {
+                take(&mut chars);
+                Token::RParen
+            }
+            ',' => {
+                take(&mut chars);
+                Token::Comma
+            }
+            ' ' | '\t' => {
+                take(&mut chars);
+                continue;
+            }
+            _ => {
+                take(&mut chars);
+                let next = chars.peek().copied();
+                let op = match (c, next) {
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('&', Some('&')) => "&&",
+                    ('|', Some('|')) => "||",
+                    ('+', _) => "+",
+                    ('-', _) => "-",
+                    ('*', _) => "*",
+                    ('/', _) => "/",
+                    ('%', _) => "%",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    ('!', _) => "!",
+                    _ => return Err(format!("Math error: unexpected character '{}' at position {}", c, start)),
+                };
+                if op.len() == 2 {
+                    take(&mut chars);
+                }
+                Token::Op(op)
+            }
+        };
+        tokens.push((token, start));
+    }
+
+    Ok(tokens)
+}
+
+/// Binding powers, loosest first: `or`, `and`, `not`, comparisons, `+ -`, `* / %`, unary
+const OR_BP: u8 = 1;
+const AND_BP: u8 = 2;
+const NOT_BP: u8 = 3;
+const COMPARE_BP: u8 = 4;
+const SUM_BP: u8 = 5;
+const PRODUCT_BP: u8 = 6;
+const UNARY_BP: u8 = 7;
+
+/// Precedence-climbing parser over the token stream
+struct Parser<'a> {
+    tokens: &'a [(Token, usize)],
+    index: usize,
+    /// Position reported when the expression ends early
+    end: usize,
+}
+
+impl Parser<'_> {
+    fn parse(mut self) -> Result<Expr, String> {
+        let expr = self.expression(0)?;
+        match self.tokens.get(self.index) {
+            None => Ok(expr),
+            Some((token, position)) => Err(format!(
+                "Math error: unexpected {} at position {}",
+                describe(token),
+                position
+            )),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(token, _)| token)
+    }
+
+    fn peek_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.index).map_or(self.end, |(_, position)| *position)
+    }
+
+    fn expression(&mut self, min_bp: u8) -> Result<Expr, String> {
+        let mut lhs = self.prefix()?;
+
+        loop {
+            let (op, bp) = match self.peek() {
+                Some(Token::Op(op @ ("||" | "&&"))) => (*op, if *op == "||" { OR_BP } else { AND_BP }),
+                Some(Token::Word(w)) if w == "or" => ("||", OR_BP),
+                Some(Token::Word(w)) if w == "and" => ("&&", AND_BP),
+                Some(Token::Op(op @ ("==" | "!=" | "<" | "<=" | ">" | ">="))) => (*op, COMPARE_BP),
+                Some(Token::Word(w)) if w == "is" => ("is", COMPARE_BP),
+                Some(Token::Word(w)) if w == "has" || w == "contains" => ("has", COMPARE_BP),
+                Some(Token::Word(w)) if w == "starts" => ("starts with", COMPARE_BP),
+                Some(Token::Word(w)) if w == "ends" => ("ends with", COMPARE_BP),
+                Some(Token::Op(op @ ("+" | "-"))) => (*op, SUM_BP),
+                Some(Token::Op(op @ ("*" | "/" | "%"))) => (*op, PRODUCT_BP),
+                _ => break,
+            };
+            if bp <= min_bp {
+                break;
+            }
+            self.index += 1;
+            if op.ends_with(" with") {
+                if !self.peek_word("with") {
+                    return Err(format!("Math error: expected '{}' at position {}", op, self.position()));
+                }
+                self.index += 1;
+            }
+
+            lhs = match op {
+                "is" => {
+                    let negated = self.peek_word("not");
+                    if negated {
+                        self.index += 1;
+                    }
+                    if self.peek_word("missing") {
+                        self.index += 1;
+                        Expr::Missing { operand: Box::new(lhs), negated }
+                    } else {
+                        let rhs = self.expression(bp)?;
+                        Expr::Binary(if negated { "!=" } else { "==" }, Box::new(lhs), Box::new(rhs))
+                    }
+                }
+                "||" => Expr::Or(Box::new(lhs), Box::new(self.expression(bp)?)),
+                "&&" => Expr::And(Box::new(lhs), Box::new(self.expression(bp)?)),
+                "has" => Expr::Has(Box::new(lhs), Box::new(self.expression(bp)?)),
+                op => Expr::Binary(op, Box::new(lhs), Box::new(self.expression(bp)?)),
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn prefix(&mut self) -> Result<Expr, String> {
+        let position = self.position();
+        let Some(token) = self.peek().cloned() else {
+            return Err(format!("Math error: expected a value at position {}", position));
+        };
+        self.index += 1;
+
+        match token {
+            Token::Literal(value) => Ok(Expr::Literal(value)),
+            Token::Var(key) => Ok(Expr::Var(ParsedKey::new(&key))),
+            Token::Word(word) if word == "not" => Ok(Expr::Not(Box::new(self.expression(NOT_BP)?))),
+            // `length` is only an operator when a value follows; on its own it is a key
+            Token::Word(word) if word == "length" && self.starts_value() => {
+                if self.peek_word("of") {
+                    self.index += 1;
+                }
+                Ok(Expr::Length(Box::new(self.expression(UNARY_BP)?)))
+            }
+            Token::Word(word) if is_operator_word(&word) => Err(format!(
+                "Math error: expected a value before '{}' at position {}",
+                word, position
+            )),
+            Token::Word(word) if matches!(self.peek(), Some(Token::LParen)) => {
+                self.index += 1;
+                let args = self.arguments(&word, position)?;
+                Ok(Expr::Call { name: word, args, position })
+            }
+            Token::Word(word) => Ok(Expr::Word(ParsedKey::new(&word))),
+            Token::Op("-") => Ok(Expr::Neg(Box::new(self.expression(UNARY_BP)?))),
+            Token::Op("+") => self.expression(UNARY_BP),
+            Token::Op("!") => Ok(Expr::Not(Box::new(self.expression(UNARY_BP)?))),
+            Token::LParen => {
+                let inner = self.expression(0)?;
+                match self.peek() {
+                    Some(Token::RParen) => {
+                        self.index += 1;
+                        Ok(inner)
+                    }
+                    _ => Err(format!(
+                        "Math error: expected ')' to close '(' from position {} at position {}",
+                        position,
+                        self.position()
+                    )),
+                }
+            }
+            token => Err(format!("Math error: unexpected {} at position {}", describe(&token), position)),
+        }
+    }
+}
+
+impl Parser<'_> {
+    /// Reads comma-separated arguments up to the `)` closing a call opened at `position`
+    fn arguments(&mut self, name: &str, position: usize) -> Result<Vec<Expr>, String> {
+        let mut args = Vec::new();
+        if matches!(self.peek(), Some(Token::RParen)) {
+            self.index += 1;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expression(0)?);
+            match self.peek() {
+                Some(Token::Comma) => self.index += 1,
+                Some(Token::RParen) => {
+                    self.index += 1;
+                    return Ok(args);
+                }
+                _ => {
+                    return Err(format!(
+                        "Math error: expected ',' or ')' in call to '{}' from position {} at position {}",
+                        name,
+                        position,
+                        self.position()
+                    ));
+                }
+            }
+        }
+    }
+
+    fn starts_value(&self) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) => !is_operator_word(word),
+            Some(Token::Op(op)) => matches!(*op, "-" | "+" | "!"),
+            Some(Token::RParen) | None => false,
+            Some(_) => true,
+        }
+    }
+}
+
+fn is_operator_word(word: &str) -> bool {
+    matches!(word, "and" | "or" | "is" | "has" | "missing" | "contains" | "starts" | "ends")
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Literal(value) => format!("value '{}'", value),
+        Token::Var(key) => format!("placeholder '{{{{{}}}}}'", key),
+        Token::Word(word) => format!("word '{}'", word),
+        Token::Op(op) => format!("operator '{}'", op),
+        Token::LParen => "'('".to_string(),
+        Token::RParen => "')'".to_string(),
+        Token::Comma => "','".to_string(),
+    }
+}
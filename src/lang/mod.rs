@@ -0,0 +1,3 @@
+pub mod expr;
+pub mod policy;
+pub mod scope;
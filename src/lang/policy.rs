@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What integer arithmetic does when a result does not fit in an `i64`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Fail the expression
+    #[default]
+    Error,
+    /// Clamp to the nearest representable integer
+    Saturate,
+    /// Two's-complement wrap-around
+    Wrap,
+    /// Keep the exact result as a wider integer, up to 128 bits
+    Promote,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(OverflowPolicy::Error),
+            "saturate" => Ok(OverflowPolicy::Saturate),
+            "wrap" => Ok(OverflowPolicy::Wrap),
+            "promote" => Ok(OverflowPolicy::Promote),
+            other => Err(format!(
+                "unknown overflow policy '{}'; expected error, saturate, wrap or promote",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OverflowPolicy::Error => "error",
+            OverflowPolicy::Saturate => "saturate",
+            OverflowPolicy::Wrap => "wrap",
+            OverflowPolicy::Promote => "promote",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How a promoted value combines with one the destination scope already holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeStrategy {
+    /// The promoted value replaces the existing one
+    #[default]
+    Overwrite,
+    /// The existing value stays
+    Keep,
+    /// Numbers are added together
+    Sum,
+    /// The larger of two numbers, or the later of two texts, is kept
+    Max,
+    /// The promoted value is added to the end of a list
+    Append,
+}
+
+impl FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "overwrite" => Ok(MergeStrategy::Overwrite),
+            "keep" => Ok(MergeStrategy::Keep),
+            "sum" => Ok(MergeStrategy::Sum),
+            "max" => Ok(MergeStrategy::Max),
+            "append" => Ok(MergeStrategy::Append),
+            other => Err(format!(
+                "unknown merge strategy '{}': expected overwrite, keep, sum, max or append",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MergeStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MergeStrategy::Overwrite => "overwrite",
+            MergeStrategy::Keep => "keep",
+            MergeStrategy::Sum => "sum",
+            MergeStrategy::Max => "max",
+            MergeStrategy::Append => "append",
+        };
+        write!(f, "{}", name)
+    }
+}
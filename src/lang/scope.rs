@@ -0,0 +1,51 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Which scope on the stack a `to <scope>` write or promotion goes to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScopeTarget {
+    /// The innermost scope
+    Local,
+    Global,
+    /// The scope this many levels out from the innermost; `parent` is `Outer(1)`
+    Outer(usize),
+    /// The innermost scope with this name, such as `event:start` or `tick:3`
+    Named(String),
+}
+
+impl FromStr for ScopeTarget {
+    type Err = String;
+
+    /// Reads `local`, `global`, `parent`, `outer <n>` or a scope name; `event:<name>` may
+    /// contain spaces, as event names do
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Err("no scope given".to_string()),
+            ["local"] => Ok(ScopeTarget::Local),
+            ["global"] => Ok(ScopeTarget::Global),
+            ["parent"] => Ok(ScopeTarget::Outer(1)),
+            ["outer", levels] => match levels.parse::<usize>() {
+                Ok(levels) if levels > 0 => Ok(ScopeTarget::Outer(levels)),
+                _ => Err(format!("`outer` expects a positive number of levels, got '{}'", levels)),
+            },
+            ["outer", ..] => Err("`outer` expects a positive number of levels".to_string()),
+            [name] => Ok(ScopeTarget::Named(name.to_string())),
+            _ if text.starts_with("event:") => Ok(ScopeTarget::Named(words.join(" "))),
+            _ => Err(format!("unknown scope '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for ScopeTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScopeTarget::Local => write!(f, "local"),
+            ScopeTarget::Global => write!(f, "global"),
+            ScopeTarget::Outer(1) => write!(f, "parent"),
+            ScopeTarget::Outer(levels) => write!(f, "outer {}", levels),
+            ScopeTarget::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 // Declare submodules
+pub mod lang;
 pub mod runtime;
 pub mod parser;
 pub mod interpreter;
@@ -1,8 +1,9 @@
 use std::env;
 use idc_runtime::parser::tokenizer::tokenize_file;
-use idc_runtime::parser::ast::build_ast;
+use idc_runtime::parser::ast::{build_ast, AstBuildError};
 use idc_runtime::parser::semantic::analyze_semantics;
 use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::parser::stmt::LowerError;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -13,6 +14,7 @@ fn main() {
     }
 
     let trace_enabled = args.contains(&"--trace".to_string());
+    let strict = args.contains(&"--strict".to_string());
     let promote_always_flag = parse_promote_always(&args);
     let command = args[1].as_str();
     let file_path = &args[2];
@@ -29,9 +31,9 @@ fn main() {
             Err(err) => eprintln!("Tokenization error: {}", err),
         }
 
-        "--ast" => parse_and_execute(file_path, false, false, None, trace_enabled, promote_always_flag),
-        "--semantics" => parse_and_execute(file_path, true, false, None, trace_enabled, promote_always_flag),
-        "--run" => parse_and_execute(file_path, true, true, tick_count, trace_enabled, promote_always_flag),
+        "--ast" => parse_and_execute(file_path, false, false, None, trace_enabled, promote_always_flag, strict),
+        "--semantics" => parse_and_execute(file_path, true, false, None, trace_enabled, promote_always_flag, strict),
+        "--run" => parse_and_execute(file_path, true, true, tick_count, trace_enabled, promote_always_flag, strict),
 
         "--reflect" => {
             if args.len() < 4 {
@@ -47,10 +49,15 @@ fn main() {
                 Ok(tokens) => match build_ast(&tokens) {
                     Ok(ast_nodes) => {
                         let mut runtime = Runtime::new(trace_enabled);
+                        runtime.set_source_name(file_path);
                         if let Some(val) = promote_always_flag {
                             runtime.set_promote_always(val);
                         }
-                        runtime.load_script(ast_nodes);
+                        runtime.set_strict(strict);
+                        if let Err(errors) = runtime.load_script(ast_nodes) {
+                            report_load_errors(file_path, &errors);
+                            return;
+                        }
                         runtime.trigger_event("start");
 
                         match reflect_type {
@@ -60,7 +67,43 @@ fn main() {
                             _ => eprintln!("Unknown reflect type: {}", reflect_type),
                         }
                     }
-                    Err(e) => eprintln!("AST build error: {:?}", e),
+                    Err(e) => report_ast_error(file_path, &e),
+                },
+                Err(e) => eprintln!("File error: {}", e),
+            }
+        }
+
+        "--query" => {
+            let Some(question) = args.get(3) else {
+                eprintln!("Usage: --query <file> \"why: did it skip ...\" [--ticks <n>] [--trace]");
+                return;
+            };
+
+            match tokenize_file(file_path) {
+                Ok(tokens) => match build_ast(&tokens) {
+                    Ok(ast_nodes) => {
+                        let mut runtime = Runtime::new(trace_enabled);
+                        runtime.set_source_name(file_path);
+                        if let Some(val) = promote_always_flag {
+                            runtime.set_promote_always(val);
+                        }
+                        runtime.set_strict(strict);
+                        if let Err(errors) = runtime.load_script(ast_nodes) {
+                            report_load_errors(file_path, &errors);
+                            return;
+                        }
+
+                        runtime.run();
+                        for _ in 0..tick_count.unwrap_or(0) {
+                            runtime.tick();
+                        }
+
+                        match runtime.query(question) {
+                            Ok(answer) => println!("{}", answer),
+                            Err(e) => eprintln!("Query error: {}", e),
+                        }
+                    }
+                    Err(e) => report_ast_error(file_path, &e),
                 },
                 Err(e) => eprintln!("File error: {}", e),
             }
@@ -77,6 +120,7 @@ fn parse_and_execute(
     tick_count: Option<u32>,
     trace_enabled: bool,
     promote_always_flag: Option<bool>,
+    strict: bool,
 ) {
     match tokenize_file(file_path) {
         Ok(tokens) => match build_ast(&tokens) {
@@ -86,7 +130,7 @@ fn parse_and_execute(
                     if !warnings.is_empty() {
                         println!("Semantic Warnings:");
                         for w in warnings {
-                            println!("{:#?}", w);
+                            println!("{}:{}", file_path, w);
                         }
                         if !auto_run {
                             return;
@@ -105,10 +149,15 @@ fn parse_and_execute(
                 }
 
                 let mut runtime = Runtime::new(trace_enabled);
+                runtime.set_source_name(file_path);
                 if let Some(val) = promote_always_flag {
                     runtime.set_promote_always(val);
                 }
-                runtime.load_script(ast_nodes);
+                runtime.set_strict(strict);
+                if let Err(errors) = runtime.load_script(ast_nodes) {
+                    report_load_errors(file_path, &errors);
+                    return;
+                }
 
                 if let Some(n) = tick_count {
                     runtime.trigger_event("start");
@@ -121,17 +170,32 @@ fn parse_and_execute(
                     runtime.run();
                 }
             }
-            Err(e) => eprintln!("AST build error: {:?}", e),
+            Err(e) => report_ast_error(file_path, &e),
         },
         Err(e) => eprintln!("File error: {}", e),
     }
 }
 
+/// Errors tied to a line read `path:line:col: ...`; the rest carry no location
+fn report_ast_error(file_path: &str, e: &AstBuildError) {
+    match e.span() {
+        Some(_) => eprintln!("AST build error: {}:{}", file_path, e),
+        None => eprintln!("AST build error: {}", e),
+    }
+}
+
+fn report_load_errors(file_path: &str, errors: &[LowerError]) {
+    eprintln!("Script rejected:");
+    for e in errors {
+        eprintln!("{}:{}", file_path, e);
+    }
+}
+
 fn parse_tick_arg(args: &[String]) -> Option<u32> {
-    if let Some(pos) = args.iter().position(|a| a == "--ticks") {
-        if let Some(val) = args.get(pos + 1) {
-            return val.parse::<u32>().ok();
-        }
+    if let Some(pos) = args.iter().position(|a| a == "--ticks")
+        && let Some(val) = args.get(pos + 1)
+    {
+        return val.parse::<u32>().ok();
     }
     None
 }
@@ -159,6 +223,8 @@ fn print_usage() {
     println!("  --run <file> --ticks <n>          Simulate N ticks using the tick engine");
     println!("  --reflect <file> <command>        Run reflection (memory, stack, log)");
     println!("       Use --as=json with memory    Show memory in JSON mode");
+    println!("  --query <file> \"<question>\"       Run, then answer why:/explain:/trace:");
     println!("  --promote-always=true|false       Enable or disable post-tick memory promotion");
+    println!("  --strict                          Treat unresolved {{{{placeholders}}}} as errors");
     println!("  --trace                           Enable scoped trace logging");
 }
@@ -1,21 +1,144 @@
-use crate::parser::tokenizer::Token;
+use crate::parser::span::Span;
+use crate::parser::token_kind::TokenKind;
+use crate::parser::tokenizer::{tokenize_reader, tokenize_str, IndentStyle, Token};
 use serde::Serialize;
+use std::fmt;
+use std::io::BufRead;
 
 /// Represents a node in the abstract syntax tree (AST)
 #[derive(Debug, Serialize, Clone)]
 pub struct AstNode {
-    pub node_type: String,
+    pub node_type: TokenKind,
     pub value: String,
     pub modifiers: Vec<String>,
     pub children: Vec<AstNode>,
+    /// `else` / `else if` attached to an `if` or `unless` node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub else_branch: Option<Box<AstNode>>,
+    pub span: Span,
+}
+
+impl AstNode {
+    fn from_token(token: &Token) -> Self {
+        AstNode {
+            node_type: token.token_type,
+            value: token.value.clone(),
+            modifiers: token.modifiers.clone(),
+            children: Vec::new(),
+            else_branch: None,
+            span: token.span,
+        }
+    }
+
+    /// Follows the clause written last on the line down to the most deeply nested node
+    fn innermost_last(&self) -> &AstNode {
+        if let Some(ref branch) = self.else_branch {
+            return branch.innermost_last();
+        }
+        self.children.last().map_or(self, AstNode::innermost_last)
+    }
+
+    fn is_block_head(&self) -> bool {
+        matches!(
+            self.node_type,
+            TokenKind::On
+                | TokenKind::Every
+                | TokenKind::If
+                | TokenKind::Unless
+                | TokenKind::While
+                | TokenKind::Else
+                | TokenKind::Atomic
+        )
+    }
+
+    /// The most deeply nested block head along the clauses written last on the line
+    fn innermost_block_head(&mut self) -> Option<&mut AstNode> {
+        let deeper_head = match self.else_branch {
+            Some(ref branch) => branch.has_block_head(),
+            None => self.children.last().is_some_and(AstNode::has_block_head),
+        };
+        if deeper_head {
+            return match self.else_branch {
+                Some(ref mut branch) => branch.innermost_block_head(),
+                None => self.children.last_mut()?.innermost_block_head(),
+            };
+        }
+        self.is_block_head().then_some(self)
+    }
+
+    fn has_block_head(&self) -> bool {
+        if self.is_block_head() {
+            return true;
+        }
+        match self.else_branch {
+            Some(ref branch) => branch.has_block_head(),
+            None => self.children.last().is_some_and(AstNode::has_block_head),
+        }
+    }
+
+    fn is_conditional(&self) -> bool {
+        matches!(self.node_type, TokenKind::If | TokenKind::Unless)
+    }
+
+    /// The last conditional in this node's `else if` chain, if it can still take an `else`
+    fn open_conditional(&mut self) -> Option<&mut AstNode> {
+        if !self.is_conditional() {
+            return None;
+        }
+        match self.else_branch {
+            None => Some(self),
+            Some(ref mut branch) => branch.open_conditional(),
+        }
+    }
 }
 
 /// Errors that can occur during AST construction
 #[derive(Debug)]
 pub enum AstBuildError {
     EmptyTokenList,
-    UnexpectedIndentation,
+    UnexpectedIndentation(Span, String),
+    MixedIndentation(Span),
     TokenParseError(String),
+    Io(String),
+}
+
+impl fmt::Display for AstBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AstBuildError::EmptyTokenList => write!(f, "no statements found"),
+            AstBuildError::UnexpectedIndentation(span, reason) => {
+                write!(f, "{}: unexpected indentation: {}", span, reason)
+            }
+            AstBuildError::MixedIndentation(span) => {
+                write!(f, "{}: indentation mixes tabs and spaces", span)
+            }
+            AstBuildError::TokenParseError(msg) => write!(f, "token parse error: {}", msg),
+            AstBuildError::Io(msg) => write!(f, "read error: {}", msg),
+        }
+    }
+}
+
+impl AstBuildError {
+    /// Source location of the error, when it can be tied to a line
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            AstBuildError::UnexpectedIndentation(span, _) | AstBuildError::MixedIndentation(span) => {
+                Some(*span)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Tokenize and build the AST for in-memory source in one step
+pub fn build_ast_from_str(source: &str) -> Result<Vec<AstNode>, AstBuildError> {
+    build_ast(&tokenize_str(source))
+}
+
+/// Tokenize and build the AST for source read from any buffered reader
+pub fn build_ast_from_reader<R: BufRead>(reader: R) -> Result<Vec<AstNode>, AstBuildError> {
+    let tokens = tokenize_reader(reader).map_err(|e| AstBuildError::Io(e.to_string()))?;
+    build_ast(&tokens)
 }
 
 /// Build the AST from a token stream based on indentation hierarchy
@@ -24,44 +147,158 @@ pub fn build_ast(tokens: &[Token]) -> Result<Vec<AstNode>, AstBuildError> {
         return Err(AstBuildError::EmptyTokenList);
     }
 
+    validate_indentation(tokens)?;
+
+    // Clauses split out of one physical line share its line number and indentation
+    let lines: Vec<&[Token]> = tokens.chunk_by(|a, b| a.span.line == b.span.line).collect();
+
     fn build_nested(
-        tokens: &[Token],
+        lines: &[&[Token]],
         start_index: usize,
         parent_indent: usize,
     ) -> Result<(Vec<AstNode>, usize), AstBuildError> {
         let mut nodes = Vec::new();
         let mut index = start_index;
 
-        while index < tokens.len() {
-            let token = &tokens[index];
+        while index < lines.len() {
+            let line = lines[index];
+            let current_indent = line[0].indentation;
 
-            if token.indentation < parent_indent {
+            if current_indent < parent_indent {
                 break;
             }
 
-            let current_indent = token.indentation;
-            let mut node = AstNode {
-                node_type: token.token_type.clone(),
-                value: token.value.clone(),
-                modifiers: token.modifiers.clone(),
-                children: Vec::new(),
-            };
+            let (mut forest, _) = build_inline(line, 0, 0);
 
-            if index + 1 < tokens.len() && tokens[index + 1].indentation > current_indent {
+            if index + 1 < lines.len() && lines[index + 1][0].indentation > current_indent {
                 let (child_nodes, consumed) =
-                    build_nested(tokens, index + 1, tokens[index + 1].indentation)?;
-                node.children = child_nodes;
+                    build_nested(lines, index + 1, lines[index + 1][0].indentation)?;
+                // An indented block belongs to the innermost block head the line left open. A line
+                // with a single clause keeps it, so lowering can say whether that statement takes one.
+                let single_clause =
+                    matches!(forest.as_slice(), [only] if only.children.is_empty() && only.else_branch.is_none());
+                if let Some(last) = forest.last_mut() {
+                    let open = if single_clause {
+                        last
+                    } else {
+                        let closing = last.innermost_last().node_type;
+                        last.innermost_block_head().ok_or_else(|| {
+                            AstBuildError::UnexpectedIndentation(
+                                lines[index + 1][0].span,
+                                format!("the line above ends with `{}`, which leaves no block open", closing),
+                            )
+                        })?
+                    };
+                    open.children.extend(child_nodes);
+                    open.children = attach_else_branches(std::mem::take(&mut open.children));
+                }
                 index = consumed;
             } else {
                 index += 1;
             }
 
+            nodes.extend(forest);
+        }
+
+        Ok((attach_else_branches(nodes), index))
+    }
+
+    /// Builds the nodes of a single line from the inline depths assigned by the tokenizer
+    fn build_inline(tokens: &[Token], start_index: usize, depth: usize) -> (Vec<AstNode>, usize) {
+        let mut nodes = Vec::new();
+        let mut index = start_index;
+
+        while index < tokens.len() && tokens[index].inline_depth >= depth {
+            let mut node = AstNode::from_token(&tokens[index]);
+            index += 1;
+
+            if index < tokens.len() && tokens[index].inline_depth > depth {
+                let (children, next) = build_inline(tokens, index, depth + 1);
+                node.children = children;
+                index = next;
+            }
+
             nodes.push(node);
         }
 
-        Ok((nodes, index))
+        (attach_else_branches(nodes), index)
     }
 
-    let (ast, _) = build_nested(tokens, 0, 0)?;
+    let (ast, _) = build_nested(&lines, 0, 0)?;
     Ok(ast)
 }
+
+/// Checks that indentation forms a consistent block structure: the first statement starts at
+/// column 1, a file uses either tabs or spaces, and every dedent returns to an enclosing level
+fn validate_indentation(tokens: &[Token]) -> Result<(), AstBuildError> {
+    let mut levels = vec![0];
+    let mut file_style = IndentStyle::None;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match (token.indent_style, file_style) {
+            (IndentStyle::Mixed, _) => return Err(AstBuildError::MixedIndentation(token.span)),
+            (IndentStyle::None, _) => {}
+            (style, IndentStyle::None) => file_style = style,
+            (style, expected) if style != expected => {
+                return Err(AstBuildError::MixedIndentation(token.span));
+            }
+            _ => {}
+        }
+
+        if index == 0 && token.indentation > 0 {
+            return Err(AstBuildError::UnexpectedIndentation(
+                token.span,
+                "the first statement must not be indented".into(),
+            ));
+        }
+
+        let current = *levels.last().unwrap_or(&0);
+        if token.indentation > current {
+            levels.push(token.indentation);
+            continue;
+        }
+
+        while levels.last().is_some_and(|&level| level > token.indentation) {
+            levels.pop();
+        }
+        if levels.last() != Some(&token.indentation) {
+            return Err(AstBuildError::UnexpectedIndentation(
+                token.span,
+                format!(
+                    "dedent to column {} does not match any enclosing block",
+                    token.span.column
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Folds `else` / `else if` siblings into the preceding `if` or `unless`. An `else` with
+/// nothing to attach to is left in place so semantic analysis can report it.
+fn attach_else_branches(nodes: Vec<AstNode>) -> Vec<AstNode> {
+    let mut attached: Vec<AstNode> = Vec::with_capacity(nodes.len());
+
+    for mut node in nodes {
+        if node.node_type != TokenKind::Else {
+            attached.push(node);
+            continue;
+        }
+
+        let Some(conditional) = attached.last_mut().and_then(AstNode::open_conditional) else {
+            attached.push(node);
+            continue;
+        };
+
+        if node.value == "if" {
+            let mut rest = node.modifiers.drain(..);
+            node.node_type = TokenKind::If;
+            node.value = rest.next().unwrap_or_default();
+            node.modifiers = rest.collect();
+        }
+        conditional.else_branch = Some(Box::new(node));
+    }
+
+    attached
+}
@@ -1,4 +1,6 @@
 pub mod tokenizer;
 pub mod ast;
 pub mod semantic;
-
+pub mod span;
+pub mod token_kind;
+pub mod stmt;
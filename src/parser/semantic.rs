@@ -1,13 +1,49 @@
 use crate::parser::ast::AstNode;
+use crate::parser::span::Span;
+use crate::parser::token_kind::TokenKind;
+use std::fmt;
 
 /// Enum for semantic-level issues discovered during validation
 #[derive(Debug, Clone)]
 pub enum SemanticWarning {
-    UnresolvedReference(String),
-    MissingRespond,
-    InvalidFlow(String),
-    UnknownNodeType(String),
-    InvalidConfiguration(String),
+    UnresolvedReference(String, Span),
+    MissingRespond(Span),
+    InvalidFlow(String, Span),
+    UnknownNodeType(String, Span),
+    InvalidConfiguration(String, Span),
+    DanglingElse(Span),
+}
+
+impl SemanticWarning {
+    /// Source location of the node that produced the warning
+    pub fn span(&self) -> Span {
+        match self {
+            SemanticWarning::UnresolvedReference(_, span)
+            | SemanticWarning::MissingRespond(span)
+            | SemanticWarning::InvalidFlow(_, span)
+            | SemanticWarning::UnknownNodeType(_, span)
+            | SemanticWarning::InvalidConfiguration(_, span)
+            | SemanticWarning::DanglingElse(span) => *span,
+        }
+    }
+
+    /// Human-readable description without the location prefix
+    pub fn message(&self) -> String {
+        match self {
+            SemanticWarning::UnresolvedReference(msg, _) => msg.clone(),
+            SemanticWarning::MissingRespond(_) => "`respond` used with no value".to_string(),
+            SemanticWarning::InvalidFlow(msg, _) => msg.clone(),
+            SemanticWarning::UnknownNodeType(value, _) => format!("unknown statement '{}'", value),
+            SemanticWarning::InvalidConfiguration(msg, _) => msg.clone(),
+            SemanticWarning::DanglingElse(_) => "`else` without a preceding `if` or `unless`".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SemanticWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span(), self.message())
+    }
 }
 
 /// Analyzes AST nodes for semantic issues like missing values, empty blocks, or unknown types
@@ -15,56 +51,67 @@ pub fn analyze_semantics(ast: &[AstNode]) -> Vec<SemanticWarning> {
     let mut warnings = Vec::new();
 
     fn walk(node: &AstNode, warnings: &mut Vec<SemanticWarning>) {
-        match node.node_type.as_str() {
-            "get" if node.value.is_empty() => warnings.push(
-                SemanticWarning::UnresolvedReference("Missing key in `get` statement".into()),
+        let span = node.span;
+        match node.node_type {
+            TokenKind::Get if node.value.is_empty() => warnings.push(
+                SemanticWarning::UnresolvedReference("Missing key in `get` statement".into(), span),
             ),
-            "remember" if node.value.is_empty() => warnings.push(
-                SemanticWarning::UnresolvedReference("`remember` used with no target".into()),
+            TokenKind::Remember if node.value.is_empty() => warnings.push(
+                SemanticWarning::UnresolvedReference("`remember` used with no target".into(), span),
             ),
-            "respond" if node.value.is_empty() => warnings.push(
-                SemanticWarning::MissingRespond,
+            TokenKind::Respond if node.value.is_empty() => warnings.push(
+                SemanticWarning::MissingRespond(span),
             ),
-            "if" | "while" => {
-                if node.children.is_empty() {
-                    warnings.push(SemanticWarning::InvalidFlow(format!(
-                        "{} block has no body",
-                        node.node_type
-                    )));
-                }
+            TokenKind::If | TokenKind::Unless | TokenKind::While | TokenKind::Else | TokenKind::Atomic
+                if node.children.is_empty() =>
+            {
+                warnings.push(SemanticWarning::InvalidFlow(
+                    format!("{} block has no body", node.node_type),
+                    span,
+                ));
             }
-            "set" => {
+            TokenKind::Set => {
                 if node.value.is_empty() {
                     warnings.push(SemanticWarning::InvalidConfiguration(
                         "`set` used with no config target".into(),
+                        span,
                     ));
                 } else if node.value == "promote_always" {
-                    if let Some(v) = node.modifiers.get(0) {
+                    if let Some(v) = node.modifiers.first() {
                         if v != "true" && v != "false" {
-                            warnings.push(SemanticWarning::InvalidConfiguration(format!(
-                                "`set promote_always` expects 'true' or 'false', got '{}'",
-                                v
-                            )));
+                            warnings.push(SemanticWarning::InvalidConfiguration(
+                                format!("`set promote_always` expects 'true' or 'false', got '{}'", v),
+                                span,
+                            ));
                         }
                     } else {
                         warnings.push(SemanticWarning::InvalidConfiguration(
                             "`set promote_always` used with no value".into(),
+                            span,
                         ));
                     }
                 }
             }
-            "unknown" => warnings.push(SemanticWarning::UnknownNodeType(node.value.clone())),
+            TokenKind::Unknown => warnings.push(SemanticWarning::UnknownNodeType(node.value.clone(), span)),
             _ => {}
         }
 
-        for child in &node.children {
-            walk(child, warnings);
+        walk_block(&node.children, warnings);
+        if let Some(branch) = &node.else_branch {
+            walk(branch, warnings);
         }
     }
 
-    for node in ast {
-        walk(node, &mut warnings);
+    /// Any `else` still sitting in a block was not attached to an `if` / `unless` by the parser
+    fn walk_block(nodes: &[AstNode], warnings: &mut Vec<SemanticWarning>) {
+        for node in nodes {
+            if node.node_type == TokenKind::Else {
+                warnings.push(SemanticWarning::DanglingElse(node.span));
+            }
+            walk(node, warnings);
+        }
     }
 
+    walk_block(ast, &mut warnings);
     warnings
 }
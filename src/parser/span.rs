@@ -0,0 +1,31 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Location of a token or node in its source: 1-based line and column plus the byte range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, start: usize, end: usize) -> Self {
+        Span { line, column, start, end }
+    }
+
+    /// Formats the span as `file:line:column`, or `line:column` when no file name is known
+    pub fn describe(&self, source_name: Option<&str>) -> String {
+        match source_name {
+            Some(name) => format!("{}:{}", name, self),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
@@ -0,0 +1,695 @@
+use crate::lang::expr::{CompiledExpr, Template};
+use crate::lang::policy::{MergeStrategy, OverflowPolicy};
+use crate::lang::scope::ScopeTarget;
+use crate::parser::ast::AstNode;
+use crate::parser::span::Span;
+use crate::parser::token_kind::TokenKind;
+use std::fmt;
+
+/// A value paired with the source location it was lowered from
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+/// An indented body of statements
+pub type Block = Vec<Spanned<Stmt>>;
+
+/// Condition of an `if` / `unless`; `unless` lowers to a negated condition
+#[derive(Debug, Clone)]
+pub struct Condition {
+    pub expr: CompiledExpr,
+    pub negated: bool,
+}
+
+/// What a `reflect` statement reports on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReflectTarget {
+    Memory,
+    Stack,
+    Log,
+    Event,
+    Flags,
+}
+
+/// Most decimal places `set precision` accepts
+pub const MAX_PRECISION: usize = 15;
+
+/// Runtime configuration changed by `set <key> <value>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Setting {
+    PromoteAlways(bool),
+    MaxIterations(u32),
+    /// Decimal places for floats printed by `respond_event`; `None` prints them in full
+    Precision(Option<usize>),
+    /// What integer arithmetic does when a result does not fit
+    Overflow(OverflowPolicy),
+    /// Whether an unresolved placeholder is an error rather than 0
+    Strict(bool),
+    /// Key patterns promotions may carry; empty allows every key
+    PromoteAllow(Vec<String>),
+    /// Key patterns promotions never carry
+    PromoteDeny(Vec<String>),
+    /// How promoted keys merge with existing ones; with `keys`, only for keys matching them
+    PromoteMerge { strategy: MergeStrategy, keys: Vec<String> },
+}
+
+/// Introspective question answered from the execution journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// `why: did it skip "<statement>"`
+    Skipped(String),
+    /// `why: did it respond "<text>"`
+    Responded(String),
+    /// `explain: last response`
+    LastResponse,
+    /// `trace: logic after <statement>`
+    TraceAfter(String),
+    /// `trace: memory <key>`
+    TraceKey(String),
+    /// `trace: <key or statement>`; a memory key with recorded writes wins
+    Trace(String),
+}
+
+impl Query {
+    /// Parses a full query such as `why: did it skip "respond Welcome"`
+    pub fn parse(text: &str) -> Result<Query, String> {
+        let text = text.trim();
+        if let Some(rest) = text.strip_prefix("why:") {
+            let rest = rest.trim_start();
+            let rest = rest.strip_prefix("did it ").or_else(|| rest.strip_prefix("did ")).unwrap_or(rest);
+            let (verb, target) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let target = target.trim().trim_matches('"').trim().to_string();
+            if target.is_empty() {
+                return Err(format!("`why: did it {}` needs a statement or text to look for", verb));
+            }
+            return match verb {
+                "skip" => Ok(Query::Skipped(target)),
+                "respond" => Ok(Query::Responded(target)),
+                other => Err(format!("unknown question 'why: did it {}'; expected skip or respond", other)),
+            };
+        }
+        if let Some(rest) = text.strip_prefix("explain:") {
+            return match rest.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                ["last", "response"] => Ok(Query::LastResponse),
+                _ => Err(format!("unknown question 'explain:{}'; expected 'last response'", rest)),
+            };
+        }
+        if let Some(rest) = text.strip_prefix("trace:") {
+            let rest = rest.trim();
+            let target = |t: &str| t.trim().trim_matches('"').trim().to_string();
+            let query = if let Some(t) = rest.strip_prefix("logic after ").or_else(|| rest.strip_prefix("after ")) {
+                Query::TraceAfter(target(t))
+            } else if let Some(t) = rest.strip_prefix("memory access of ").or_else(|| rest.strip_prefix("memory ")) {
+                Query::TraceKey(target(t))
+            } else {
+                Query::Trace(target(rest))
+            };
+            return match query {
+                Query::TraceAfter(ref t) | Query::TraceKey(ref t) | Query::Trace(ref t) if t.is_empty() => {
+                    Err("`trace:` needs a statement or memory key to follow".to_string())
+                }
+                query => Ok(query),
+            };
+        }
+        Err(format!("'{}' is not a query; start it with `why:`, `explain:` or `trace:`", text))
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Query::Skipped(target) => write!(f, "why: did it skip \"{}\"", target),
+            Query::Responded(target) => write!(f, "why: did it respond \"{}\"", target),
+            Query::LastResponse => write!(f, "explain: last response"),
+            Query::TraceAfter(target) => write!(f, "trace: logic after {}", target),
+            Query::TraceKey(key) => write!(f, "trace: memory \"{}\"", key),
+            Query::Trace(target) => write!(f, "trace: {}", target),
+        }
+    }
+}
+
+/// Typed statement produced by lowering an `AstNode`; expressions and placeholder text are
+/// compiled here, once, rather than every time the statement runs
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    On { event: String, body: Block },
+    Every { interval: u32, body: Block },
+    If { cond: Condition, then: Block, else_: Block },
+    While { cond: CompiledExpr, body: Block },
+    Respond { text: Template },
+    RespondEvent { expr: CompiledExpr },
+    /// `trigger <event> [with <key> <value> ...]`; payload values are evaluated when triggered
+    Trigger { event: Template, payload: Vec<(String, CompiledExpr)> },
+    Load { expr: CompiledExpr, target: String, scope: Option<ScopeTarget> },
+    /// `save memory to "<file>"`: writes a snapshot of the runtime
+    SaveMemory { path: Template },
+    /// `load memory from "<file>"`: restores global memory, tick and settings from a snapshot
+    LoadMemory { path: Template },
+    /// `promote <keys> [to <scope>] [when <expr>]`: merges matching keys of the current scope
+    /// into an outer one under the promotion policy
+    Promote { keys: Vec<String>, target: ScopeTarget, when: Option<CompiledExpr> },
+    Remember { key: String },
+    Wait,
+    Reflect { target: ReflectTarget, mode: Option<String> },
+    Set(Setting),
+    Stop,
+    Break,
+    Continue,
+    /// Body whose memory writes are kept only if it finishes without `stop`, `rollback` or a
+    /// failed statement
+    Atomic { body: Block },
+    /// Abandons the enclosing `atomic` block and discards its writes
+    Rollback,
+    Query(Query),
+    /// Any other keyword (or unknown word); executing it runs its body
+    Action { kind: TokenKind, value: String, modifiers: Vec<String>, body: Block },
+}
+
+impl Stmt {
+    /// Keyword the statement was written with
+    pub fn keyword(&self) -> TokenKind {
+        match self {
+            Stmt::On { .. } => TokenKind::On,
+            Stmt::Every { .. } => TokenKind::Every,
+            Stmt::If { cond, .. } if cond.negated => TokenKind::Unless,
+            Stmt::If { .. } => TokenKind::If,
+            Stmt::While { .. } => TokenKind::While,
+            Stmt::Respond { .. } => TokenKind::Respond,
+            Stmt::RespondEvent { .. } => TokenKind::RespondEvent,
+            Stmt::Trigger { .. } => TokenKind::Trigger,
+            Stmt::Load { .. } | Stmt::LoadMemory { .. } => TokenKind::Load,
+            Stmt::SaveMemory { .. } => TokenKind::Save,
+            Stmt::Promote { .. } => TokenKind::Promote,
+            Stmt::Remember { .. } => TokenKind::Remember,
+            Stmt::Wait => TokenKind::Wait,
+            Stmt::Reflect { .. } => TokenKind::Reflect,
+            Stmt::Set(_) => TokenKind::Set,
+            Stmt::Stop => TokenKind::Stop,
+            Stmt::Break => TokenKind::Break,
+            Stmt::Continue => TokenKind::Continue,
+            Stmt::Atomic { .. } => TokenKind::Atomic,
+            Stmt::Rollback => TokenKind::Rollback,
+            Stmt::Query(Query::LastResponse) => TokenKind::Explain,
+            Stmt::Query(Query::TraceAfter(_) | Query::TraceKey(_) | Query::Trace(_)) => TokenKind::Trace,
+            Stmt::Query(_) => TokenKind::Why,
+            Stmt::Action { kind, .. } => *kind,
+        }
+    }
+}
+
+/// Source-like rendering of the statement header (without its body)
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stmt::On { event, .. } => write!(f, "on {}", event),
+            Stmt::Every { interval, .. } => write!(f, "every {}", interval),
+            Stmt::If { cond, .. } => write!(f, "{} {}", self.keyword(), cond.expr),
+            Stmt::While { cond, .. } => write!(f, "while {}", cond),
+            Stmt::Respond { text } => write!(f, "respond {}", text),
+            Stmt::RespondEvent { expr } => write!(f, "respond_event {}", expr),
+            Stmt::Trigger { event, payload } => {
+                write!(f, "trigger {}", event)?;
+                if !payload.is_empty() {
+                    write!(f, " with")?;
+                    for (key, value) in payload {
+                        write!(f, " {} {}", key, value)?;
+                    }
+                }
+                Ok(())
+            }
+            Stmt::Load { expr, target, scope } => {
+                write!(f, "load {} as {}", expr, target)?;
+                match scope {
+                    Some(scope) => write!(f, " to {}", scope),
+                    None => Ok(()),
+                }
+            }
+            Stmt::SaveMemory { path } => write!(f, "save memory to \"{}\"", path),
+            Stmt::LoadMemory { path } => write!(f, "load memory from \"{}\"", path),
+            Stmt::Promote { keys, target, when } => {
+                write!(f, "promote {} to {}", keys.join(", "), target)?;
+                match when {
+                    Some(cond) => write!(f, " when {}", cond),
+                    None => Ok(()),
+                }
+            }
+            Stmt::Remember { key } => write!(f, "remember {}", key),
+            Stmt::Wait => write!(f, "wait"),
+            Stmt::Reflect { target, mode } => {
+                write!(f, "reflect {}", format!("{:?}", target).to_lowercase())?;
+                match mode {
+                    Some(mode) => write!(f, " {}", mode),
+                    None => Ok(()),
+                }
+            }
+            Stmt::Set(Setting::PromoteAlways(value)) => write!(f, "set promote_always {}", value),
+            Stmt::Set(Setting::MaxIterations(limit)) => write!(f, "set max_iterations {}", limit),
+            Stmt::Set(Setting::Precision(Some(places))) => write!(f, "set precision {}", places),
+            Stmt::Set(Setting::Precision(None)) => write!(f, "set precision auto"),
+            Stmt::Set(Setting::Overflow(policy)) => write!(f, "set overflow {}", policy),
+            Stmt::Set(Setting::Strict(value)) => write!(f, "set strict {}", value),
+            Stmt::Set(Setting::PromoteAllow(keys)) => write!(f, "set promote_allow {}", patterns_or_none(keys)),
+            Stmt::Set(Setting::PromoteDeny(keys)) => write!(f, "set promote_deny {}", patterns_or_none(keys)),
+            Stmt::Set(Setting::PromoteMerge { strategy, keys }) if keys.is_empty() => {
+                write!(f, "set promote_merge {}", strategy)
+            }
+            Stmt::Set(Setting::PromoteMerge { strategy, keys }) => {
+                write!(f, "set promote_merge {} for {}", strategy, keys.join(", "))
+            }
+            Stmt::Stop => write!(f, "stop"),
+            Stmt::Break => write!(f, "break"),
+            Stmt::Continue => write!(f, "continue"),
+            Stmt::Atomic { .. } => write!(f, "atomic"),
+            Stmt::Rollback => write!(f, "rollback"),
+            Stmt::Query(query) => write!(f, "{}", query),
+            Stmt::Action { kind, value, modifiers, .. } => {
+                write!(f, "{}", kind)?;
+                for part in std::iter::once(value).chain(modifiers).filter(|p| !p.is_empty()) {
+                    write!(f, " {}", part)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A statement that cannot be executed as written
+#[derive(Debug, Clone)]
+pub struct LowerError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl LowerError {
+    fn new(span: Span, message: impl Into<String>) -> Self {
+        LowerError { span, message: message.into() }
+    }
+}
+
+impl fmt::Display for LowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+/// Lowers the generic AST into typed statements, collecting every malformed statement
+pub fn lower(nodes: &[AstNode]) -> Result<Block, Vec<LowerError>> {
+    let mut errors = Vec::new();
+    let block = lower_block(nodes, Enclosing::default(), &mut errors);
+    if errors.is_empty() { Ok(block) } else { Err(errors) }
+}
+
+/// Constructs around a statement that flow keywords act on; a handler starts afresh
+#[derive(Debug, Clone, Copy, Default)]
+struct Enclosing {
+    /// A `while` for `break` / `continue`
+    in_loop: bool,
+    /// An `atomic` block for `rollback`
+    in_atomic: bool,
+}
+
+fn lower_block(nodes: &[AstNode], enclosing: Enclosing, errors: &mut Vec<LowerError>) -> Block {
+    nodes
+        .iter()
+        .filter_map(|node| match lower_node(node, enclosing, errors) {
+            Ok(stmt) => Some(Spanned { node: stmt, span: node.span }),
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn lower_node(node: &AstNode, enclosing: Enclosing, errors: &mut Vec<LowerError>) -> Result<Stmt, LowerError> {
+    let span = node.span;
+    let inner = match node.node_type {
+        TokenKind::While => Enclosing { in_loop: true, ..enclosing },
+        TokenKind::Atomic => Enclosing { in_atomic: true, ..enclosing },
+        TokenKind::On | TokenKind::Every => Enclosing::default(),
+        _ => enclosing,
+    };
+    let body = lower_block(&node.children, inner, errors);
+
+    let stmt = match node.node_type {
+        TokenKind::On => {
+            let event = event_name(&header_words(node));
+            if event.is_empty() {
+                return Err(LowerError::new(span, "`on` needs an event name"));
+            }
+            Stmt::On { event, body }
+        }
+        TokenKind::Every => match node.value.trim_matches('"').parse::<u32>() {
+            Ok(interval) if interval > 0 => Stmt::Every { interval, body },
+            _ => {
+                return Err(LowerError::new(
+                    span,
+                    format!("invalid interval in 'every {}': expected a positive tick count", node.value),
+                ));
+            }
+        },
+        TokenKind::If | TokenKind::Unless => {
+            if node.value.is_empty() {
+                return Err(LowerError::new(span, format!("`{}` needs a condition", node.node_type)));
+            }
+            let expr = std::iter::once(&node.value)
+                .chain(&node.modifiers)
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(" ");
+            Stmt::If {
+                cond: Condition { expr: CompiledExpr::new(&expr), negated: node.node_type == TokenKind::Unless },
+                then: body,
+                else_: lower_else(node.else_branch.as_deref(), enclosing, errors)?,
+            }
+        }
+        TokenKind::While => {
+            if node.value.is_empty() {
+                return Err(LowerError::new(span, "`while` needs a condition"));
+            }
+            let cond = std::iter::once(&node.value)
+                .chain(&node.modifiers)
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(" ");
+            Stmt::While { cond: CompiledExpr::new(&cond), body }
+        }
+        TokenKind::Break | TokenKind::Continue if !enclosing.in_loop => {
+            return Err(LowerError::new(
+                span,
+                format!("`{}` used outside of a `while` loop", node.node_type),
+            ));
+        }
+        TokenKind::Atomic => Stmt::Atomic { body },
+        TokenKind::Rollback if !enclosing.in_atomic => {
+            return Err(LowerError::new(span, "`rollback` used outside of an `atomic` block"));
+        }
+        TokenKind::Rollback => Stmt::Rollback,
+        TokenKind::Break => Stmt::Break,
+        TokenKind::Continue => Stmt::Continue,
+        TokenKind::Else => {
+            return Err(LowerError::new(span, "`else` without a preceding `if` or `unless`"));
+        }
+        TokenKind::Respond => Stmt::Respond {
+            text: Template::new(
+                &std::iter::once(&node.value)
+                    .chain(&node.modifiers)
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            ),
+        },
+        TokenKind::RespondEvent => Stmt::RespondEvent { expr: CompiledExpr::new(&node.value) },
+        TokenKind::Trigger => lower_trigger(node)?,
+        TokenKind::Why | TokenKind::Explain | TokenKind::Trace => {
+            let text = std::iter::once(&node.node_type.to_string())
+                .chain(std::iter::once(&node.value))
+                .chain(&node.modifiers)
+                .cloned()
+                .collect::<Vec<String>>()
+                .join(" ");
+            Stmt::Query(Query::parse(&text).map_err(|message| LowerError::new(span, message))?)
+        }
+        TokenKind::Load if node.value == "memory" && node.modifiers.first().is_some_and(|m| m == "from") => Stmt::LoadMemory { path: snapshot_path(node, "from")? },
+        TokenKind::Load => lower_load(node)?,
+        TokenKind::Save if node.value == "memory" => Stmt::SaveMemory { path: snapshot_path(node, "to")? },
+        TokenKind::Promote => lower_promote(node)?,
+        TokenKind::Remember => {
+            if node.value.is_empty() {
+                return Err(LowerError::new(span, "`remember` used with no target"));
+            }
+            Stmt::Remember { key: node.value.clone() }
+        }
+        TokenKind::Wait => Stmt::Wait,
+        TokenKind::Reflect => {
+            let target = match node.value.as_str() {
+                "memory" => ReflectTarget::Memory,
+                "stack" => ReflectTarget::Stack,
+                "log" => ReflectTarget::Log,
+                "event" => ReflectTarget::Event,
+                "flags" => ReflectTarget::Flags,
+                other => {
+                    return Err(LowerError::new(
+                        span,
+                        format!("unknown reflect target '{}': expected memory, stack, log, event or flags", other),
+                    ));
+                }
+            };
+            Stmt::Reflect { target, mode: node.modifiers.first().cloned() }
+        }
+        TokenKind::Set => Stmt::Set(lower_setting(node)?),
+        TokenKind::Stop => Stmt::Stop,
+        kind => Stmt::Action {
+            kind,
+            value: node.value.clone(),
+            modifiers: node.modifiers.clone(),
+            body,
+        },
+    };
+
+    // Only block heads run what is indented under them; anything else would drop it silently
+    let takes_body = matches!(
+        stmt,
+        Stmt::On { .. } | Stmt::Every { .. } | Stmt::If { .. } | Stmt::While { .. } | Stmt::Atomic { .. } | Stmt::Action { .. }
+    );
+    if !takes_body && !node.children.is_empty() {
+        return Err(LowerError::new(span, format!("`{}` does not take an indented body", node.node_type)));
+    }
+
+    Ok(stmt)
+}
+
+/// An attached `else` contributes its body; an `else if` becomes a nested conditional
+fn lower_else(
+    branch: Option<&AstNode>,
+    enclosing: Enclosing,
+    errors: &mut Vec<LowerError>,
+) -> Result<Block, LowerError> {
+    match branch {
+        None => Ok(Vec::new()),
+        Some(node) if node.node_type == TokenKind::Else => Ok(lower_block(&node.children, enclosing, errors)),
+        Some(node) => Ok(vec![Spanned { node: lower_node(node, enclosing, errors)?, span: node.span }]),
+    }
+}
+
+/// `load <expr> [as <key>] [to <scope>]` or `load <key> from <expr> [to <scope>]`; the
+/// scope runs to the next `as` or `from`, so `to outer 2` and `to event:user login` work
+fn lower_load(node: &AstNode) -> Result<Stmt, LowerError> {
+    if node.value.is_empty() {
+        return Err(LowerError::new(node.span, "`load` needs a value"));
+    }
+
+    let mut expr = node.value.clone();
+    let mut target = node.value.clone();
+    let mut scope = None;
+    let mut parts = node.modifiers.iter().peekable();
+
+    while let Some(part) = parts.next() {
+        let argument = match part.as_str() {
+            "as" | "from" | "to" => parts.next().ok_or_else(|| {
+                LowerError::new(node.span, format!("`load ... {}` is missing its argument", part))
+            })?,
+            _ => continue,
+        };
+        match part.as_str() {
+            "as" => target = argument.clone(),
+            "from" => expr = argument.clone(),
+            _ => {
+                let mut words = vec![argument.as_str()];
+                while let Some(word) = parts.next_if(|word| *word != "as" && *word != "from") {
+                    words.push(word);
+                }
+                scope = Some(scope_target(node, &words.join(" "))?);
+            }
+        }
+    }
+
+    Ok(Stmt::Load { expr: CompiledExpr::new(&expr), target, scope })
+}
+
+/// `promote <keys> [to <scope>] [when <expr>]`; the scope defaults to `global`
+fn lower_promote(node: &AstNode) -> Result<Stmt, LowerError> {
+    let words: Vec<&String> = std::iter::once(&node.value).chain(&node.modifiers).collect();
+    let (head, when) = match words.iter().position(|word| *word == "when") {
+        Some(at) => (&words[..at], Some(&words[at + 1..])),
+        None => (&words[..], None),
+    };
+    let (keys, target) = match head.iter().position(|word| *word == "to") {
+        Some(at) => {
+            let scope = head[at + 1..].iter().map(|word| word.as_str()).collect::<Vec<&str>>().join(" ");
+            (&head[..at], scope_target(node, &scope)?)
+        }
+        None => (head, ScopeTarget::Global),
+    };
+
+    let keys = key_patterns(keys);
+    if keys.is_empty() {
+        return Err(LowerError::new(node.span, "`promote` needs the keys to promote"));
+    }
+    let when = match when {
+        Some([]) => return Err(LowerError::new(node.span, "`promote ... when` is missing its condition")),
+        Some(cond) => Some(CompiledExpr::new(&cond.iter().map(|word| word.as_str()).collect::<Vec<&str>>().join(" "))),
+        None => None,
+    };
+    Ok(Stmt::Promote { keys, target, when })
+}
+
+fn scope_target(node: &AstNode, text: &str) -> Result<ScopeTarget, LowerError> {
+    text.parse::<ScopeTarget>()
+        .map_err(|e| LowerError::new(node.span, format!("`{} ... to`: {}", node.node_type, e)))
+}
+
+/// Key patterns written as `a, b c`; quotes are dropped
+fn key_patterns(words: &[&String]) -> Vec<String> {
+    words
+        .iter()
+        .flat_map(|word| word.split(','))
+        .map(|pattern| pattern.trim().trim_matches('"'))
+        .filter(|pattern| !pattern.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn patterns_or_none(keys: &[String]) -> String {
+    if keys.is_empty() { "none".to_string() } else { keys.join(", ") }
+}
+
+/// The file named after `memory to` / `memory from`
+fn snapshot_path(node: &AstNode, preposition: &str) -> Result<Template, LowerError> {
+    match node.modifiers.as_slice() {
+        [word, path] if word == preposition => Ok(Template::new(path.trim_matches('"'))),
+        _ => Err(LowerError::new(
+            node.span,
+            format!("expected `{} memory {} \"<file>\"`", node.node_type, preposition),
+        )),
+    }
+}
+
+/// Splits a statement header back into words, keeping quoted phrases together
+fn header_words(node: &AstNode) -> Vec<String> {
+    let text = std::iter::once(&node.value)
+        .chain(&node.modifiers)
+        .cloned()
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Event names span every word of the header, so `on user login` and `on "user login"` match
+fn event_name(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| word.trim_matches('"'))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn lower_trigger(node: &AstNode) -> Result<Stmt, LowerError> {
+    let words = header_words(node);
+    let (name, payload) = match words.iter().position(|word| word == "with") {
+        Some(at) => (&words[..at], &words[at + 1..]),
+        None => (&words[..], &[][..]),
+    };
+
+    let event = event_name(name);
+    if event.is_empty() {
+        return Err(LowerError::new(node.span, "`trigger` needs an event name"));
+    }
+    if payload.is_empty() && name.len() < words.len() {
+        return Err(LowerError::new(node.span, "`trigger ... with` is missing its payload"));
+    }
+    if payload.len() % 2 != 0 {
+        return Err(LowerError::new(
+            node.span,
+            format!("payload key '{}' has no value", payload[payload.len() - 1]),
+        ));
+    }
+
+    let payload = payload
+        .chunks(2)
+        .map(|pair| (pair[0].trim_matches('"').to_string(), CompiledExpr::new(&pair[1])))
+        .collect();
+    Ok(Stmt::Trigger { event: Template::new(&event), payload })
+}
+
+fn lower_setting(node: &AstNode) -> Result<Setting, LowerError> {
+    let value = node.modifiers.first().map(|v| v.to_lowercase());
+    match (node.value.as_str(), value.as_deref()) {
+        ("", _) => Err(LowerError::new(node.span, "`set` used with no config target")),
+        ("promote_always", Some("true")) => Ok(Setting::PromoteAlways(true)),
+        ("promote_always", Some("false")) => Ok(Setting::PromoteAlways(false)),
+        ("promote_always", _) => Err(LowerError::new(
+            node.span,
+            "`set promote_always` expects 'true' or 'false'",
+        )),
+        ("max_iterations", limit) => match limit.map(str::parse::<u32>) {
+            Some(Ok(limit)) if limit > 0 => Ok(Setting::MaxIterations(limit)),
+            _ => Err(LowerError::new(
+                node.span,
+                "`set max_iterations` expects a positive whole number",
+            )),
+        },
+        ("precision", Some("auto")) => Ok(Setting::Precision(None)),
+        ("precision", places) => match places.map(str::parse::<usize>) {
+            Some(Ok(places)) if places <= MAX_PRECISION => Ok(Setting::Precision(Some(places))),
+            _ => Err(LowerError::new(
+                node.span,
+                format!("`set precision` expects 0 to {} decimal places or 'auto'", MAX_PRECISION),
+            )),
+        },
+        ("strict", Some("true")) => Ok(Setting::Strict(true)),
+        ("strict", Some("false")) => Ok(Setting::Strict(false)),
+        ("strict", _) => Err(LowerError::new(node.span, "`set strict` expects 'true' or 'false'")),
+        ("overflow", policy) => policy
+            .unwrap_or_default()
+            .parse::<OverflowPolicy>()
+            .map(Setting::Overflow)
+            .map_err(|e| LowerError::new(node.span, format!("`set overflow`: {}", e))),
+        ("promote_allow" | "promote_deny", _) => {
+            let words: Vec<&String> = node.modifiers.iter().collect();
+            let keys = match key_patterns(&words).as_slice() {
+                [] => return Err(LowerError::new(node.span, format!("`set {}` expects key patterns or 'none'", node.value))),
+                [none] if none == "none" => Vec::new(),
+                keys => keys.to_vec(),
+            };
+            Ok(if node.value == "promote_allow" { Setting::PromoteAllow(keys) } else { Setting::PromoteDeny(keys) })
+        }
+        ("promote_merge", strategy) => {
+            let strategy = strategy
+                .unwrap_or_default()
+                .parse::<MergeStrategy>()
+                .map_err(|e| LowerError::new(node.span, format!("`set promote_merge`: {}", e)))?;
+            let keys = match node.modifiers.get(1..).unwrap_or_default() {
+                [] => Vec::new(),
+                [word, rest @ ..] if word == "for" && !rest.is_empty() => key_patterns(&rest.iter().collect::<Vec<&String>>()),
+                _ => return Err(LowerError::new(node.span, "expected `set promote_merge <strategy> [for <keys>]`")),
+            };
+            Ok(Setting::PromoteMerge { strategy, keys })
+        }
+        (other, _) => Err(LowerError::new(node.span, format!("unknown setting '{}'", other))),
+    }
+}
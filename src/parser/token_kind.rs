@@ -0,0 +1,157 @@
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Broad grouping of keywords, as described in `docs/glossary.md`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum TokenCategory {
+    Action,
+    Condition,
+    Modifier,
+    Event,
+    Memory,
+    System,
+    Flow,
+    Entity,
+}
+
+impl fmt::Display for TokenCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Error returned when a word is not one of the runtime's keywords
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKeyword(pub String);
+
+impl fmt::Display for UnknownKeyword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' is not an IDC keyword", self.0)
+    }
+}
+
+macro_rules! token_kinds {
+    ($($variant:ident => $text:literal, $category:ident;)*) => {
+        /// Every keyword a statement can start with (the Foundational 40 plus runtime extensions)
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+        #[serde(rename_all = "snake_case")]
+        pub enum TokenKind {
+            $($variant,)*
+            Unknown,
+        }
+
+        impl TokenKind {
+            /// All keyword kinds, excluding `Unknown`
+            pub const KEYWORDS: &'static [TokenKind] = &[$(TokenKind::$variant,)*];
+
+            /// The keyword as written in source
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(TokenKind::$variant => $text,)*
+                    TokenKind::Unknown => "unknown",
+                }
+            }
+
+            /// Glossary category of the keyword; `None` for unrecognised words
+            pub fn category(&self) -> Option<TokenCategory> {
+                match self {
+                    $(TokenKind::$variant => Some(TokenCategory::$category),)*
+                    TokenKind::Unknown => None,
+                }
+            }
+
+            /// Classifies a word, falling back to `Unknown` for anything that is not a keyword
+            pub fn from_keyword(word: &str) -> TokenKind {
+                match word {
+                    $($text => TokenKind::$variant,)*
+                    _ => TokenKind::Unknown,
+                }
+            }
+        }
+    };
+}
+
+token_kinds! {
+    On => "on", Event;
+    Every => "every", Event;
+    If => "if", Condition;
+    Unless => "unless", Condition;
+    Is => "is", Condition;
+    Has => "has", Condition;
+    Load => "load", Action;
+    Save => "save", Action;
+    Remember => "remember", Memory;
+    Get => "get", Memory;
+    Respond => "respond", Action;
+    Show => "show", Action;
+    Log => "log", Action;
+    Retry => "retry", Modifier;
+    Else => "else", Modifier;
+    Then => "then", Modifier;
+    Wait => "wait", Modifier;
+    As => "as", Memory;
+    With => "with", Modifier;
+    From => "from", Modifier;
+    To => "to", Modifier;
+    Authorize => "authorize", System;
+    Purge => "purge", System;
+    Reset => "reset", System;
+    Stop => "stop", Flow;
+    Continue => "continue", Flow;
+    Alert => "alert", Action;
+    Check => "check", Action;
+    Verify => "verify", Action;
+    Update => "update", Action;
+    Delete => "delete", Action;
+    Create => "create", Action;
+    Connect => "connect", Action;
+    Disconnect => "disconnect", Action;
+    Use => "use", Flow;
+    Define => "define", Memory;
+    Alias => "alias", Memory;
+    Capture => "capture", Memory;
+    Test => "test", Action;
+    Print => "print", Action;
+    Route => "route", Entity;
+    True => "true", Condition;
+    False => "false", Condition;
+    While => "while", Flow;
+    Break => "break", Flow;
+    RespondEvent => "respond_event", Action;
+    Start => "start", Event;
+    End => "end", Event;
+    Trigger => "trigger", Event;
+    Event => "event", Event;
+    Reflect => "reflect", System;
+    Set => "set", System;
+    Why => "why:", System;
+    Explain => "explain:", System;
+    Trace => "trace:", System;
+    Atomic => "atomic", Flow;
+    Rollback => "rollback", Flow;
+    Promote => "promote", Memory;
+}
+
+impl TokenKind {
+    pub fn is_keyword(&self) -> bool {
+        *self != TokenKind::Unknown
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TokenKind {
+    type Err = UnknownKeyword;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match TokenKind::from_keyword(s) {
+            TokenKind::Unknown if s != "unknown" => Err(UnknownKeyword(s.to_string())),
+            kind => Ok(kind),
+        }
+    }
+}
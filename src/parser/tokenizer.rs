@@ -1,93 +1,275 @@
-use std::collections::HashSet;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
+use crate::parser::span::Span;
+use crate::parser::token_kind::TokenKind;
+
+/// Which whitespace characters make up a line's indentation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    None,
+    Spaces,
+    Tabs,
+    Mixed,
+}
+
+impl IndentStyle {
+    fn of(whitespace: &str) -> Self {
+        let tabs = whitespace.contains('\t');
+        let spaces = whitespace.chars().any(|c| c != '\t');
+        match (tabs, spaces) {
+            (false, false) => IndentStyle::None,
+            (false, true) => IndentStyle::Spaces,
+            (true, false) => IndentStyle::Tabs,
+            (true, true) => IndentStyle::Mixed,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Token {
-    pub token_type: String,
+    pub token_type: TokenKind,
     pub value: String,
     pub modifiers: Vec<String>,
     pub indentation: usize,
+    pub indent_style: IndentStyle,
+    /// Nesting added within a single line by `,` clauses and `→` chains (0 for the line's lead clause)
+    pub inline_depth: usize,
+    pub span: Span,
 }
 
 /// Tokenizes an .idc file line-by-line using indentation rules and quoted string awareness
 pub fn tokenize_file(file_path: &str) -> io::Result<Vec<Token>> {
     let file = File::open(file_path)
         .map_err(|e| io::Error::new(io::ErrorKind::NotFound, format!("Error opening file: {}", e)))?;
-    let reader = BufReader::new(file);
+    tokenize_reader(BufReader::new(file))
+}
+
+/// Tokenizes IDC source held in memory (snippets, REPL input, tests)
+pub fn tokenize_str(source: &str) -> Vec<Token> {
+    tokenize_reader(source.as_bytes()).expect("reading from an in-memory buffer cannot fail")
+}
+
+/// Tokenizes IDC source from any buffered reader
+pub fn tokenize_reader<R: BufRead>(mut reader: R) -> io::Result<Vec<Token>> {
     let mut tokens = Vec::new();
+    let mut buffer = String::new();
+    let mut line_number = 0;
+    let mut line_start = 0;
+
+    loop {
+        buffer.clear();
+        let bytes_read = reader.read_line(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        line_number += 1;
+        let offset = line_start;
+        line_start += bytes_read;
 
-    let foundational_40: HashSet<&str> = [
-        "on", "every", "if", "unless", "is", "has", "load", "save", "remember", "get", "respond",
-        "show", "log", "retry", "else", "then", "wait", "as", "with", "from", "to", "authorize",
-        "purge", "reset", "stop", "continue", "alert", "check", "verify", "update", "delete",
-        "create", "connect", "disconnect", "use", "define", "alias", "capture", "test", "print", "route",
-        "true", "false", "while", "break", "respond_event", "start", "end", "trigger", "event", "reflect",
-        "set"
-    ].iter().copied().collect();
-
-    for (_line_number, line) in reader.lines().enumerate() {
-        let line = line?;
+        let line = buffer.trim_end_matches(['\n', '\r']);
         let trimmed = line.trim_start();
         let indentation = line.len() - trimmed.len();
-
+        let indent_style = IndentStyle::of(&line[..indentation]);
         if trimmed.is_empty() || trimmed.starts_with("//") {
             continue;
         }
 
-        let mut parts = Vec::new();
-        let mut current = String::new();
-        let mut in_quotes = false;
-        let mut paren_depth = 0;
+        let column = line[..indentation].chars().count() + 1;
+        for clause in split_clauses(trimmed) {
+            let parts = split_parts(clause.text);
 
-        for c in trimmed.chars() {
-            match c {
-                '"' => {
-                    in_quotes = !in_quotes;
-                    current.push(c);
-                }
-                '(' if !in_quotes => {
-                    paren_depth += 1;
-                    current.push(c);
-                }
-                ')' if !in_quotes => {
-                    paren_depth -= 1;
-                    current.push(c);
-                }
-                ' ' if !in_quotes && paren_depth == 0 => {
-                    if !current.is_empty() {
-                        parts.push(current.clone());
-                        current.clear();
-                    }
+            let token_type = parts
+                .first()
+                .map(|first| TokenKind::from_keyword(first))
+                .unwrap_or(TokenKind::Unknown);
+
+            let value = parts.get(1).cloned().unwrap_or_default();
+            let modifiers = if parts.len() > 2 {
+                parts[2..].to_vec()
+            } else {
+                vec![]
+            };
+
+            let start = offset + indentation + clause.offset;
+            let span = Span::new(
+                line_number,
+                column + trimmed[..clause.offset].chars().count(),
+                start,
+                start + clause.text.len(),
+            );
+
+            tokens.push(Token {
+                token_type,
+                value,
+                modifiers,
+                indentation,
+                indent_style,
+                inline_depth: clause.depth,
+                span,
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+/// Splits a clause into space-separated parts, keeping quoted strings and parenthesised groups whole
+fn split_parts(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut paren_depth = 0;
+
+    for c in text.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            '(' if !in_quotes => {
+                paren_depth += 1;
+                current.push(c);
+            }
+            ')' if !in_quotes => {
+                paren_depth -= 1;
+                current.push(c);
+            }
+            ' ' if !in_quotes && paren_depth == 0 => {
+                if !current.is_empty() {
+                    parts.push(current.clone());
+                    current.clear();
                 }
-                _ => current.push(c),
             }
+            _ => current.push(c),
         }
-        if !current.is_empty() {
-            parts.push(current);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    parts
+}
+
+/// One statement carved out of a physical line, with its byte offset in the trimmed line
+struct Clause<'a> {
+    text: &'a str,
+    offset: usize,
+    depth: usize,
+}
+
+/// Desugars `→` / `->` chains and leading comma clauses into statements with inline depths.
+///
+/// A block head (`on`, `every`, `if`, `unless`, `while`, `else`) opens a level: whatever follows
+/// it after a comma or arrow becomes its body. Other chained clauses are sequential siblings,
+/// and `else` closes back to the `if` / `unless` it belongs to, so
+/// `if error, retry once → else alert "failed"` yields an `if` and an `else` at the same depth.
+fn split_clauses(line: &str) -> Vec<Clause<'_>> {
+    let mut clauses = Vec::new();
+    // One entry per open head; `true` marks a conditional an `else` can close back to
+    let mut heads: Vec<bool> = Vec::new();
+
+    for (offset, segment) in split_top_level(line, Separator::Arrow) {
+        push_segment(offset, segment, &mut heads, &mut clauses);
+    }
+
+    clauses
+}
+
+fn push_segment<'a>(offset: usize, segment: &'a str, heads: &mut Vec<bool>, clauses: &mut Vec<Clause<'a>>) {
+    let text = segment.trim_start();
+    let offset = offset + (segment.len() - text.len());
+    let text = text.trim_end();
+    if text.is_empty() {
+        return;
+    }
+
+    let first_word = text.split([' ', '\t', ',']).next().unwrap_or_default();
+    let kind = TokenKind::from_keyword(first_word);
+    let after_first = &text[first_word.len()..];
+    let rest = after_first.trim_start_matches([' ', '\t', ',']);
+    let rest_offset = offset + first_word.len() + (after_first.len() - rest.len());
+    let is_else_if = kind == TokenKind::Else
+        && TokenKind::from_keyword(rest.split([' ', '\t']).next().unwrap_or_default()) == TokenKind::If;
+
+    if kind == TokenKind::Else {
+        if let Some(position) = heads.iter().rposition(|&conditional| conditional) {
+            heads.truncate(position);
         }
+        if !is_else_if {
+            clauses.push(Clause { text: first_word, offset, depth: heads.len() });
+            heads.push(false);
+            push_segment(rest_offset, rest, heads, clauses);
+            return;
+        }
+    }
 
-        let first = parts.get(0).cloned().unwrap_or_else(|| "unknown".to_string());
-        let token_type = if foundational_40.contains(first.as_str()) {
-            first
-        } else {
-            "unknown".to_string()
-        };
+    let is_head = is_else_if
+        || matches!(
+            kind,
+            TokenKind::On
+                | TokenKind::Every
+                | TokenKind::If
+                | TokenKind::Unless
+                | TokenKind::While
+                | TokenKind::Atomic
+        );
+    if !is_head {
+        clauses.push(Clause { text, offset, depth: heads.len() });
+        return;
+    }
 
-        let value = parts.get(1).cloned().unwrap_or_default();
-        let modifiers = if parts.len() > 2 {
-            parts[2..].to_vec()
-        } else {
-            vec![]
-        };
+    let mut pieces = split_top_level(text, Separator::Comma).into_iter();
+    let (_, head) = pieces.next().unwrap_or((0, text));
+    clauses.push(Clause { text: head.trim_end(), offset, depth: heads.len() });
+    heads.push(is_else_if || matches!(kind, TokenKind::If | TokenKind::Unless));
 
-        tokens.push(Token {
-            token_type,
-            value,
-            modifiers,
-            indentation,
-        });
+    // Only the first comma separates the head from its body; later ones belong to the body
+    if let Some((body_offset, _)) = pieces.next() {
+        push_segment(offset + body_offset, &text[body_offset..], heads, clauses);
     }
+}
 
-    Ok(tokens)
-}
\ No newline at end of file
+#[derive(Clone, Copy, PartialEq)]
+enum Separator {
+    Arrow,
+    Comma,
+}
+
+/// Splits on arrows or commas that sit outside quotes and parentheses, returning each piece
+/// with its byte offset
+fn split_top_level(text: &str, separator: Separator) -> Vec<(usize, &str)> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut paren_depth = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let width = match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                continue;
+            }
+            '(' if !in_quotes => {
+                paren_depth += 1;
+                continue;
+            }
+            ')' if !in_quotes => {
+                paren_depth -= 1;
+                continue;
+            }
+            _ if in_quotes || paren_depth != 0 => continue,
+            '→' if separator == Separator::Arrow => c.len_utf8(),
+            '-' if separator == Separator::Arrow && chars.peek().map(|&(_, next)| next) == Some('>') => {
+                chars.next();
+                2
+            }
+            ',' if separator == Separator::Comma => 1,
+            _ => continue,
+        };
+        pieces.push((start, &text[start..i]));
+        start = i + width;
+    }
+    pieces.push((start, &text[start..]));
+
+    pieces
+}
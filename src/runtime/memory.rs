@@ -1,16 +1,110 @@
-use std::collections::HashMap;
-use crate::interpreter::math::resolve_math_placeholders;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use crate::interpreter::math::{EvalOptions, Lookup, Rendered, Template};
+pub use crate::lang::scope::ScopeTarget;
+use crate::parser::span::Span;
+use crate::runtime::path::{self, ParsedKey, Path, PathError};
+use crate::runtime::promotion::{self, Outcome, Promotion, PromotionPolicy};
+use crate::runtime::value::Value;
 use crate::utils::logger::Logger;
 
-#[derive(Clone)]
+/// Writes kept before the oldest are dropped
+pub const WRITE_LOG_LIMIT: usize = 4096;
+
+/// One assignment to a memory key, kept so `trace:` can show where a value came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryWrite {
+    pub key: String,
+    pub value: Value,
+    /// Name of the scope that received the value
+    pub scope: String,
+    pub tick: u32,
+    /// Statement that caused the write; `None` when the host wrote it directly
+    pub span: Option<Span>,
+    /// How a promotion produced the value, such as `promoted from [tick:3] (sum)`
+    pub via: Option<String>,
+}
+
+/// A `ScopeTarget` that does not match the stack as it stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScopeError {
+    /// No scope of this name is open; `chain` is the stack at the time
+    NotOnStack { name: String, chain: String },
+    /// `outer <levels>` reaches past `global`
+    TooFar { levels: usize, chain: String },
+}
+
+impl fmt::Display for ScopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScopeError::NotOnStack { name, chain } => {
+                write!(f, "scope '{}' is not on the stack [{}]", name, chain)
+            }
+            ScopeError::TooFar { levels, chain } => {
+                write!(f, "no scope {} level(s) out on the stack [{}]", levels, chain)
+            }
+        }
+    }
+}
+
+/// Why `set_target` wrote nothing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriteError {
+    Scope(ScopeError),
+    Path(PathError),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::Scope(e) => write!(f, "{}", e),
+            WriteError::Path(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<ScopeError> for WriteError {
+    fn from(e: ScopeError) -> Self {
+        WriteError::Scope(e)
+    }
+}
+
+impl From<PathError> for WriteError {
+    fn from(e: PathError) -> Self {
+        WriteError::Path(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MemoryScope {
     pub name: String,
-    pub data: HashMap<String, String>,
+    pub data: HashMap<String, Value>,
+}
+
+/// What `begin` saved for `rollback` to put back
+struct Checkpoint {
+    scopes: Vec<HashMap<String, Value>>,
+    /// `written` at the time, so the writes made since can be dropped from the log
+    written: usize,
 }
 
 pub struct MemoryStore {
     stack: Vec<MemoryScope>,
     promote_on_tick: bool,
+    writes: VecDeque<MemoryWrite>,
+    /// Writes ever logged, including those since dropped from the front of `writes`
+    written: usize,
+    origin: (u32, Option<Span>),
+    /// Saved by `begin`, innermost transaction last
+    checkpoints: Vec<Checkpoint>,
+    policy: PromotionPolicy,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MemoryStore {
@@ -21,7 +115,90 @@ impl MemoryStore {
                 data: HashMap::new(),
             }],
             promote_on_tick: false,
+            writes: VecDeque::new(),
+            written: 0,
+            origin: (0, None),
+            checkpoints: Vec::new(),
+            policy: PromotionPolicy::default(),
+        }
+    }
+
+    /// The scope stack, outermost (`global`) first
+    pub fn scopes(&self) -> &[MemoryScope] {
+        &self.stack
+    }
+
+    /// Closes every scope but `global`, as when resuming from a snapshot between ticks
+    pub fn close_handler_scopes(&mut self) {
+        self.stack.truncate(1);
+    }
+
+    /// Replaces only the global scope's contents, leaving any running handler scopes alone.
+    /// Each restored key is recorded in the write log as `restored from <source>`
+    pub fn restore_global(&mut self, data: HashMap<String, Value>, source: &str) {
+        let mut keys: Vec<&String> = data.keys().collect();
+        keys.sort_unstable();
+        for key in keys {
+            self.log_write(key, &data[key], "global".to_string(), Some(format!("restored from {}", source)));
         }
+        self.stack[0].data = data;
+    }
+
+    /// Starts a transaction: the scopes open now can be put back as they are by `rollback`.
+    /// Transactions nest; each `begin` is ended by one `commit` or `rollback`
+    pub fn begin(&mut self) {
+        self.checkpoints.push(Checkpoint {
+            scopes: self.stack.iter().map(|scope| scope.data.clone()).collect(),
+            written: self.written,
+        });
+    }
+
+    /// Keeps every write made since the matching `begin`
+    pub fn commit(&mut self) {
+        self.checkpoints.pop();
+    }
+
+    /// Discards every write made since the matching `begin`, including promotions into
+    /// `global`, and drops them from the write log; returns how many keys were changed back
+    pub fn rollback(&mut self) -> usize {
+        let Some(saved) = self.checkpoints.pop() else {
+            return 0;
+        };
+        let discarded = self.written - saved.written;
+        self.writes.truncate(self.writes.len().saturating_sub(discarded));
+        self.written = saved.written;
+
+        let mut restored = 0;
+        for (scope, data) in self.stack.iter_mut().zip(saved.scopes) {
+            restored += data.iter().filter(|(key, value)| scope.data.get(*key) != Some(*value)).count();
+            restored += scope.data.keys().filter(|key| !data.contains_key(*key)).count();
+            scope.data = data;
+        }
+        restored
+    }
+
+    /// Whether a transaction is open
+    pub fn in_transaction(&self) -> bool {
+        !self.checkpoints.is_empty()
+    }
+
+    /// Tick and statement that subsequent writes are attributed to
+    pub fn set_origin(&mut self, tick: u32, span: Option<Span>) {
+        self.origin = (tick, span);
+    }
+
+    fn log_write(&mut self, key: &str, value: &Value, scope: String, via: Option<String>) {
+        if self.writes.len() >= WRITE_LOG_LIMIT {
+            self.writes.pop_front();
+        }
+        let (tick, span) = self.origin;
+        self.written += 1;
+        self.writes.push_back(MemoryWrite { key: key.to_string(), value: value.clone(), scope, tick, span, via });
+    }
+
+    /// Every recorded write to `key`, oldest first
+    pub fn writes_to<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a MemoryWrite> {
+        self.writes.iter().filter(move |w| w.key == key)
     }
 
     pub fn set_promote_on_tick(&mut self, enable: bool, mut logger: Option<&mut Logger>) {
@@ -65,11 +242,46 @@ impl MemoryStore {
         }
     }
 
-    pub fn set(&mut self, key: &str, value: &str, mut logger: Option<&mut Logger>) {
-        if let Some(current_scope) = self.stack.last_mut() {
-            let previous = current_scope.data.insert(key.to_string(), value.to_string());
+    /// Writes `value` at a key or path (`user.roles[0]`) in the scope at `index`. A nested
+    /// write to a root that only an outer scope holds copies that root in first, so the
+    /// inner scope shadows it whole rather than with a partial map.
+    fn write_at(&mut self, index: usize, key: &str, value: Value) -> Result<Option<Value>, PathError> {
+        let path = Path::parse(key).filter(Path::is_nested);
+        let Some(path) = path else {
+            let scope = &mut self.stack[index];
+            let previous = scope.data.insert(key.to_string(), value.clone());
+            let name = scope.name.clone();
+            self.log_write(key, &value, name, None);
+            return Ok(previous);
+        };
 
-            if let Some(ref mut log) = logger {
+        let mut root = match self.stack[index].data.get(&path.root) {
+            Some(existing) => existing.clone(),
+            None => self.stack[..index]
+                .iter()
+                .rev()
+                .find_map(|scope| scope.data.get(&path.root).cloned())
+                .unwrap_or(Value::Null),
+        };
+        let previous = path::get_in(&root, &path.rest).cloned();
+        path::set_in(&mut root, &path.rest, value.clone(), &path.root)?;
+
+        let scope = &mut self.stack[index];
+        scope.data.insert(path.root, root);
+        let name = scope.name.clone();
+        self.log_write(key, &value, name, None);
+        Ok(previous)
+    }
+
+    pub fn set(&mut self, key: &str, value: Value, mut logger: Option<&mut Logger>) {
+        let index = self.stack.len() - 1;
+        match self.write_at(index, key, value.clone()) {
+            Err(e) => {
+                if let Some(ref mut log) = logger {
+                    log.warn(&format!("[MEM] Cannot set {}: {}", key, e));
+                }
+            }
+            Ok(previous) => if let Some(ref mut log) = logger {
                 let scope = self.format_scope_chain();
                 match previous {
                     Some(old) if old != value => {
@@ -80,83 +292,154 @@ impl MemoryStore {
                     }
                     _ => {}
                 }
-            }
+            },
         }
     }
 
-    pub fn get(&self, key: &str) -> Option<String> {
+    /// Looks up a key or path in the innermost scope that holds it (or the path's root)
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.get_in(key, path::nested_path(key).as_ref())
+    }
+
+    /// `get` for a key whose path was parsed ahead of time
+    fn get_in(&self, key: &str, path: Option<&Path>) -> Option<Value> {
         for scope in self.stack.iter().rev() {
             if let Some(value) = scope.data.get(key) {
                 return Some(value.clone());
             }
+            if let Some(path) = path
+                && let Some(root) = scope.data.get(&path.root)
+            {
+                return path::get_in(root, &path.rest).cloned();
+            }
         }
         None
     }
 
-    pub fn resolve_placeholders(&self, input: &str) -> String {
-        let memory = self.flatten_scoped();
-        resolve_math_placeholders(input, &memory)
+    pub fn resolve_placeholders(&self, input: &str, options: &EvalOptions) -> Result<Rendered, String> {
+        Template::new(input).render(self, options)
     }
 
-    pub fn flatten_scoped(&self) -> HashMap<String, String> {
+    /// Every visible key; inner scopes shadow outer ones, as in `get`
+    pub fn flatten_map(&self) -> HashMap<String, Value> {
         let mut flat = HashMap::new();
         for scope in self.stack.iter() {
             for (k, v) in &scope.data {
-                flat.entry(k.clone()).or_insert(v.clone());
+                flat.insert(k.clone(), v.clone());
             }
         }
         flat
     }
 
-    pub fn flatten_map(&self) -> HashMap<String, String> {
-        let mut flat = HashMap::new();
-        for scope in self.stack.iter().rev() {
-            for (k, v) in &scope.data {
-                flat.insert(k.clone(), v.clone());
-            }
+    /// Which keys promotions carry into the destination and how they merge there
+    pub fn set_promotion_policy(&mut self, policy: PromotionPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn promotion_policy(&self) -> &PromotionPolicy {
+        &self.policy
+    }
+
+    /// Position of the scope called `name`, innermost first
+    pub fn scope_index(&self, name: &str) -> Option<usize> {
+        self.stack.iter().rposition(|s| s.name == name)
+    }
+
+    /// Position on the stack of the scope `target` names
+    pub fn resolve(&self, target: &ScopeTarget) -> Result<usize, ScopeError> {
+        let innermost = self.stack.len() - 1;
+        match target {
+            ScopeTarget::Local => Ok(innermost),
+            ScopeTarget::Global => Ok(0),
+            ScopeTarget::Outer(levels) => innermost.checked_sub(*levels).ok_or_else(|| ScopeError::TooFar {
+                levels: *levels,
+                chain: self.format_scope_chain(),
+            }),
+            ScopeTarget::Named(name) => self.scope_index(name).ok_or_else(|| ScopeError::NotOnStack {
+                name: name.clone(),
+                chain: self.format_scope_chain(),
+            }),
         }
-        flat
     }
 
-    pub fn promote_all(&mut self) {
+    /// Promotes the top scope into `global` under the promotion policy
+    pub fn promote_all(&mut self) -> Vec<Promotion> {
         if self.stack.len() >= 2 {
-            let top_data = self.stack.last().map(|s| s.data.clone()).unwrap_or_default();
+            self.promote_between(self.stack.len() - 1, 0, None)
+        } else {
+            Vec::new()
+        }
+    }
 
-            if let Some(global) = self.stack.first_mut() {
-                for (key, val) in top_data {
-                    global.data.insert(key, val);
+    pub fn promote_scope(&mut self, from: &str, to: &str, mut logger: Option<&mut Logger>) -> Vec<Promotion> {
+        match (self.scope_index(from), self.scope_index(to)) {
+            (Some(from), Some(to)) => {
+                let promotions = self.promote_between(from, to, None);
+                if let Some(ref mut log) = logger {
+                    log.trace(&format!("[PROMOTE] {} → {}", self.stack[from].name, self.stack[to].name));
+                    promotion::log_promotions(&promotions, "", log);
                 }
+                promotions
+            }
+            _ => {
+                if let Some(ref mut log) = logger {
+                    log.warn(&format!("[PROMOTE] Failed: {} → {} (missing scope)", from, to));
+                }
+                Vec::new()
             }
         }
     }
 
-    pub fn promote_scope(&mut self, from: &str, to: &str, mut logger: Option<&mut Logger>) {
-        let from_scope = self.stack.iter().find(|s| s.name == from).cloned();
-        let to_scope = self.stack.iter_mut().find(|s| s.name == to);
-
-        if let (Some(from), Some(to)) = (from_scope, to_scope) {
-            for (k, v) in from.data {
-                to.data.insert(k, v);
-            }
+    /// Merges the keys of the scope at `from` into the scope at `to`, key by key in sorted
+    /// order, as the promotion policy allows. `keys` limits the promotion to keys matching
+    /// one of its patterns. Every merged key is recorded in the write log.
+    pub fn promote_between(&mut self, from: usize, to: usize, keys: Option<&[String]>) -> Vec<Promotion> {
+        let (from_name, to_name) = (self.stack[from].name.clone(), self.stack[to].name.clone());
+        let mut entries: Vec<(String, Value)> = self.stack[from]
+            .data
+            .iter()
+            .filter(|(key, _)| keys.is_none_or(|patterns| patterns.iter().any(|p| promotion::matches(p, key))))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
 
-            if let Some(ref mut log) = logger {
-                log.trace(&format!("[PROMOTE] {} → {}", from.name, to.name));
-            }
-        } else if let Some(ref mut log) = logger {
-            log.warn(&format!("[PROMOTE] Failed: {} → {} (missing scope)", from, to));
+        let mut promotions = Vec::new();
+        for (key, value) in entries {
+            let outcome = match self.policy.permits(&key) {
+                Err(reason) => Outcome::Denied(reason),
+                Ok(()) => {
+                    let strategy = self.policy.strategy_for(&key);
+                    let merged = match self.stack[to].data.get(&key) {
+                        Some(existing) => strategy.merge(existing, &value),
+                        None => Ok(value),
+                    };
+                    match merged {
+                        Ok(value) => {
+                            self.stack[to].data.insert(key.clone(), value.clone());
+                            let via = format!("promoted from [{}] ({})", from_name, strategy);
+                            self.log_write(&key, &value, to_name.clone(), Some(via));
+                            Outcome::Merged { strategy, value }
+                        }
+                        Err(error) => Outcome::Conflict { strategy, error },
+                    }
+                }
+            };
+            promotions.push(Promotion { key, from: from_name.clone(), to: to_name.clone(), outcome });
         }
+        promotions
     }
 
     pub fn promote_if<F>(&mut self, condition: F, mut logger: Option<&mut Logger>)
     where
-        F: FnOnce(&HashMap<String, String>) -> bool,
+        F: FnOnce(&HashMap<String, Value>) -> bool,
     {
-        if let Some(top) = self.stack.last() {
-            if condition(&top.data) {
-                self.promote_all();
-                if let Some(ref mut log) = logger {
-                    log.trace("[PROMOTE] Conditional promotion triggered");
-                }
+        if let Some(top) = self.stack.last()
+            && condition(&top.data)
+        {
+            let promotions = self.promote_all();
+            if let Some(ref mut log) = logger {
+                log.trace("[PROMOTE] Conditional promotion triggered");
+                promotion::log_promotions(&promotions, "", log);
             }
         }
     }
@@ -173,11 +456,11 @@ impl MemoryStore {
             .join(" > ")
     }
 
-    pub fn get_local_map(&self) -> HashMap<String, String> {
+    pub fn get_local_map(&self) -> HashMap<String, Value> {
         self.stack.last().map(|s| s.data.clone()).unwrap_or_default()
     }
 
-    pub fn get_all_scopes(&self) -> Vec<(String, HashMap<String, String>)> {
+    pub fn get_all_scopes(&self) -> Vec<(String, HashMap<String, Value>)> {
         self.stack.iter().map(|s| (s.name.clone(), s.data.clone())).collect()
     }
 
@@ -185,33 +468,47 @@ impl MemoryStore {
         self.stack.last()
     }
 
-    pub fn all_scopes(&self) -> Vec<(&str, &HashMap<String, String>)> {
+    pub fn all_scopes(&self) -> Vec<(&str, &HashMap<String, Value>)> {
         self.stack.iter().map(|s| (s.name.as_str(), &s.data)).collect()
     }
 
+    /// Writes `value` into the scope `target` names, or the innermost one when it is `None`;
+    /// fails without writing when that scope is not on the stack or the path cannot be set
     pub fn set_target(
         &mut self,
         key: &str,
-        value: &str,
-        target_scope: Option<&str>,
+        value: Value,
+        target: Option<&ScopeTarget>,
         mut logger: Option<&mut Logger>,
-    ) {
-        let scope_name = target_scope.unwrap_or("local");
-        let target = match scope_name {
-            "local" => self.stack.last_mut(),
-            "global" => self.stack.first_mut(),
-            _ => None,
-        };
-
-        if let Some(scope) = target {
-            scope.data.insert(key.to_string(), value.to_string());
-            if let Some(ref mut log) = logger {
-                log.trace(&format!("[MEM] Target set: [{}] {} = {}", scope_name, key, value));
-            }
+    ) -> Result<(), WriteError> {
+        let target = target.unwrap_or(&ScopeTarget::Local);
+        let index = self.resolve(target)?;
+        self.write_at(index, key, value.clone())?;
+        if let Some(ref mut log) = logger {
+            log.trace(&format!("[MEM] Target set: [{}] {} = {}", self.stack[index].name, key, value));
         }
+        Ok(())
     }
 
     pub fn scope_contains(&self, key: &str) -> bool {
-        self.stack.last().map_or(false, |s| s.data.contains_key(key))
+        self.stack.last().is_some_and(|s| s.data.contains_key(key))
+    }
+}
+
+/// Expressions read memory through the scope stack directly, without flattening it
+impl Lookup for MemoryStore {
+    fn lookup(&self, key: &str) -> Option<Value> {
+        self.get(key)
+    }
+
+    fn lookup_parsed(&self, key: &ParsedKey) -> Option<Value> {
+        self.get_in(&key.name, key.path.as_ref())
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.stack.iter().flat_map(|scope| scope.data.keys().cloned()).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys
     }
 }
@@ -1 +1,5 @@
 pub mod memory;
+pub mod value;
+pub mod path;
+pub mod snapshot;
+pub mod promotion;
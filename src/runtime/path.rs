@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use crate::runtime::value::Value;
+
+/// One step of a memory path: a map key or a list index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A parsed memory path such as `user.roles[0]`; the first segment is always a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    pub root: String,
+    pub rest: Vec<Segment>,
+}
+
+/// A key as written in an expression, with its path parsed once when the expression is
+/// compiled so lookups do not parse it again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedKey {
+    pub name: String,
+    /// Only set for keys that go beyond a plain name, such as `user.roles[0]`
+    pub path: Option<Path>,
+}
+
+/// Why a value could not be written at a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// An intermediate value is not a map or list
+    NotAContainer { path: String, found: &'static str },
+    IndexOutOfRange { path: String, index: usize, len: usize },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::NotAContainer { path, found } => {
+                write!(f, "cannot descend into '{}': it holds a {}", path, found)
+            }
+            PathError::IndexOutOfRange { path, index, len } => {
+                write!(f, "index {} is out of range for '{}' (length {})", index, path, len)
+            }
+        }
+    }
+}
+
+impl Path {
+    /// Parses `a.b[2].c`; returns `None` for anything that is not a well-formed path
+    pub fn parse(text: &str) -> Option<Path> {
+        let mut segments = Vec::new();
+        for part in text.split('.') {
+            let (name, mut indexes) = match part.find('[') {
+                Some(at) => (&part[..at], &part[at..]),
+                None => (part, ""),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == ':') {
+                return None;
+            }
+            segments.push(Segment::Key(name.to_string()));
+            while !indexes.is_empty() {
+                let close = indexes.find(']')?;
+                let index = indexes.get(1..close)?.trim().parse::<usize>().ok()?;
+                segments.push(Segment::Index(index));
+                indexes = &indexes[close + 1..];
+                if !indexes.is_empty() && !indexes.starts_with('[') {
+                    return None;
+                }
+            }
+        }
+
+        let mut segments = segments.into_iter();
+        match segments.next() {
+            Some(Segment::Key(root)) => Some(Path { root, rest: segments.collect() }),
+            _ => None,
+        }
+    }
+
+    /// Whether the path goes beyond a plain key
+    pub fn is_nested(&self) -> bool {
+        !self.rest.is_empty()
+    }
+}
+
+impl ParsedKey {
+    pub fn new(name: &str) -> Self {
+        ParsedKey { name: name.to_string(), path: nested_path(name) }
+    }
+}
+
+/// The path a key names when it goes beyond a plain name; plain names skip the parse
+pub fn nested_path(key: &str) -> Option<Path> {
+    if !key.contains(['.', '[']) {
+        return None;
+    }
+    Path::parse(key).filter(Path::is_nested)
+}
+
+/// Follows `segments` into `value`
+pub fn get_in<'a>(value: &'a Value, segments: &[Segment]) -> Option<&'a Value> {
+    segments.iter().try_fold(value, |current, segment| match (current, segment) {
+        (Value::Map(entries), Segment::Key(key)) => entries.get(key),
+        (Value::List(items), Segment::Index(index)) => items.get(*index),
+        _ => None,
+    })
+}
+
+/// Writes `new_value` at `segments` inside `value`, creating maps for missing keys; a list
+/// index may address an existing item or append at the end
+pub fn set_in(value: &mut Value, segments: &[Segment], new_value: Value, walked: &str) -> Result<(), PathError> {
+    let Some((segment, rest)) = segments.split_first() else {
+        *value = new_value;
+        return Ok(());
+    };
+
+    if matches!(value, Value::Null) && matches!(segment, Segment::Key(_)) {
+        *value = Value::Map(BTreeMap::new());
+    }
+
+    match (value, segment) {
+        (Value::Map(entries), Segment::Key(key)) => {
+            let walked = format!("{}.{}", walked, key);
+            let child = entries.entry(key.clone()).or_insert(Value::Null);
+            set_in(child, rest, new_value, &walked)
+        }
+        (Value::List(items), Segment::Index(index)) => {
+            let walked = format!("{}[{}]", walked, index);
+            let len = items.len();
+            if *index == len {
+                items.push(Value::Null);
+            }
+            match items.get_mut(*index) {
+                Some(child) => set_in(child, rest, new_value, &walked),
+                None => Err(PathError::IndexOutOfRange { path: walked, index: *index, len }),
+            }
+        }
+        (other, _) => Err(PathError::NotAContainer { path: walked.to_string(), found: other.type_name() }),
+    }
+}
+
+/// Looks up a key or path in a flat map of visible keys; an exact key wins over a path
+pub fn lookup(memory: &HashMap<String, Value>, key: &str) -> Option<Value> {
+    lookup_in(memory, key, nested_path(key).as_ref())
+}
+
+/// `lookup` for a key whose path was parsed ahead of time
+pub fn lookup_in(memory: &HashMap<String, Value>, key: &str, path: Option<&Path>) -> Option<Value> {
+    if let Some(value) = memory.get(key) {
+        return Some(value.clone());
+    }
+    let path = path?;
+    get_in(memory.get(&path.root)?, &path.rest).cloned()
+}
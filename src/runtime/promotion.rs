@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+pub use crate::lang::policy::MergeStrategy;
+use crate::runtime::value::Value;
+use crate::utils::logger::Logger;
+
+impl MergeStrategy {
+    /// The value the destination ends up with; `Err` when the two values cannot be combined
+    /// this way, in which case the destination is left alone
+    pub fn merge(self, existing: &Value, incoming: &Value) -> Result<Value, String> {
+        match (self, existing, incoming) {
+            (MergeStrategy::Overwrite, _, _) => Ok(incoming.clone()),
+            (MergeStrategy::Keep, _, _) => Ok(existing.clone()),
+            (MergeStrategy::Sum, a, b) if let (Some(x), Some(y)) = (a.as_integer(), b.as_integer()) => x
+                .checked_add(y)
+                .map(Value::integer)
+                .ok_or_else(|| format!("integer overflow in '{} + {}'", a, b)),
+            (MergeStrategy::Sum, a, b) => Ok(Value::Float(number(a)? + number(b)?)),
+            (MergeStrategy::Max, a, b) if let (Some(x), Some(y)) = (a.as_integer(), b.as_integer()) => {
+                Ok(if y > x { b.clone() } else { a.clone() })
+            }
+            (MergeStrategy::Max, Value::Str(a), Value::Str(b)) => Ok(Value::Str(a.max(b).clone())),
+            (MergeStrategy::Max, a, b) => Ok(if number(b)? > number(a)? { b.clone() } else { a.clone() }),
+            (MergeStrategy::Append, Value::List(items), Value::List(more)) => {
+                Ok(Value::List(items.iter().chain(more).cloned().collect()))
+            }
+            (MergeStrategy::Append, Value::List(items), value) => {
+                Ok(Value::List(items.iter().chain(std::iter::once(value)).cloned().collect()))
+            }
+            (MergeStrategy::Append, value, more) => Ok(Value::List(vec![value.clone(), more.clone()])),
+        }
+    }
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Int(n) => Ok(*n as f64),
+        Value::BigInt(n) => Ok(*n as f64),
+        Value::Float(x) => Ok(*x),
+        other => Err(format!("{} '{}' is not a number", other.type_name(), other)),
+    }
+}
+
+/// Which keys may leave a scope when it is promoted, and how each one merges. Patterns
+/// match whole keys; `*` stands for any run of characters and `?` for one character.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PromotionPolicy {
+    /// Keys that may be promoted; empty allows every key
+    pub allow: Vec<String>,
+    /// Keys that are never promoted, even when `allow` matches them
+    pub deny: Vec<String>,
+    /// Strategy for keys no rule names
+    pub merge: MergeStrategy,
+    /// Strategies for particular keys; the first matching pattern wins
+    pub rules: Vec<(String, MergeStrategy)>,
+}
+
+impl PromotionPolicy {
+    /// `Err` names why `key` is held back
+    pub fn permits(&self, key: &str) -> Result<(), String> {
+        if let Some(pattern) = self.deny.iter().find(|pattern| matches(pattern, key)) {
+            return Err(format!("denied by '{}'", pattern));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|pattern| matches(pattern, key)) {
+            return Err("not in the allow list".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn strategy_for(&self, key: &str) -> MergeStrategy {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| matches(pattern, key))
+            .map_or(self.merge, |(_, strategy)| *strategy)
+    }
+}
+
+/// Whether `key` fits a pattern of literal characters, `*` and `?`
+pub fn matches(pattern: &str, key: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let key: Vec<char> = key.chars().collect();
+    let (mut p, mut k) = (0, 0);
+    // Where the last `*` was seen, and the key position it currently stands up to
+    let mut star: Option<(usize, usize)> = None;
+    while k < key.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, k));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match star {
+                Some((star_p, star_k)) => {
+                    star = Some((star_p, star_k + 1));
+                    p = star_p + 1;
+                    k = star_k + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// What happened to one key when a scope was promoted
+#[derive(Debug, Clone, PartialEq)]
+pub struct Promotion {
+    pub key: String,
+    pub from: String,
+    pub to: String,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The destination now holds `value`
+    Merged { strategy: MergeStrategy, value: Value },
+    /// The policy held the key back
+    Denied(String),
+    /// The strategy could not combine the values; the destination kept its own
+    Conflict { strategy: MergeStrategy, error: String },
+}
+
+impl fmt::Display for Promotion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: [{}] → [{}] ", self.key, self.from, self.to)?;
+        match &self.outcome {
+            Outcome::Merged { strategy, value } => write!(f, "= {} ({})", value, strategy),
+            Outcome::Denied(reason) => write!(f, "held back, {}", reason),
+            Outcome::Conflict { strategy, error } => write!(f, "kept existing value, {} failed: {}", strategy, error),
+        }
+    }
+}
+
+/// Traces each key a promotion considered; a merge conflict that kept the existing value is
+/// a warning instead. `at` prefixes every line, e.g. with the location of a `promote`.
+pub fn log_promotions(promotions: &[Promotion], at: &str, logger: &mut Logger) {
+    for promotion in promotions {
+        match promotion.outcome {
+            Outcome::Merged { .. } | Outcome::Denied(_) => logger.trace(&format!("{}[PROMOTE] {}", at, promotion)),
+            Outcome::Conflict { .. } => logger.warn(&format!("{}[PROMOTE] {}", at, promotion)),
+        }
+    }
+}
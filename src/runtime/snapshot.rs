@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use crate::lang::policy::OverflowPolicy;
+use crate::runtime::memory::MemoryScope;
+use crate::runtime::promotion::PromotionPolicy;
+
+/// Format version written into every snapshot; older or newer files are rejected
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// A checkpoint of a runtime: the full scope stack, tick position and settings. Handlers,
+/// the journal and the memory write log are not included; they come from the script and
+/// from running it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Scopes outermost first; the first is always `global`
+    pub scopes: Vec<MemoryScope>,
+    pub tick: u32,
+    pub event_stack: Vec<String>,
+    pub flags: Flags,
+}
+
+/// Runtime settings carried by a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Flags {
+    pub promote_on_tick: bool,
+    pub max_iterations: u32,
+    pub precision: Option<usize>,
+    pub overflow: OverflowPolicy,
+    pub strict: bool,
+    /// Absent from snapshots written before promotion policies existed
+    #[serde(default)]
+    pub promotion: PromotionPolicy,
+}
+
+/// Why a snapshot could not be written or read
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    Version(u32),
+    /// The snapshot has no scopes, or its first scope is not `global`
+    MissingGlobal,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::Format(e) => write!(f, "not a valid snapshot: {}", e),
+            SnapshotError::Version(found) => {
+                write!(f, "snapshot version {} is not supported (expected {})", found, SNAPSHOT_VERSION)
+            }
+            SnapshotError::MissingGlobal => write!(f, "snapshot does not start with the global scope"),
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        SnapshotError::Format(e)
+    }
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses and checks a snapshot written by `to_json`
+    pub fn from_json(text: &str) -> Result<Snapshot, SnapshotError> {
+        let snapshot: Snapshot = serde_json::from_str(text)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::Version(snapshot.version));
+        }
+        if snapshot.scopes.first().is_none_or(|scope| scope.name != "global") {
+            return Err(SnapshotError::MissingGlobal);
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save_to(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Snapshot, SnapshotError> {
+        Snapshot::from_json(&fs::read_to_string(path)?)
+    }
+}
@@ -25,6 +25,12 @@ pub struct Logger {
     trace_limit: usize,
 }
 
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Logger {
     pub fn new() -> Self {
         Logger {
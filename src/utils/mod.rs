@@ -1,2 +1,3 @@
 pub mod logger;
+#[allow(clippy::module_inception)]
 pub mod utils;
@@ -1,30 +1,44 @@
-use crate::interpreter::math::evaluate_expression;
+use crate::interpreter::math::{CompiledExpr, EvalOptions, Evaluation};
 use crate::runtime::memory::MemoryStore;
 
 /// Resolves an expression or literal key using the current memory.
-/// If the string is quoted or contains math symbols, it will be evaluated.
-/// Returns Result<String, String> to unify error handling.
+/// If the string contains math symbols, comparisons or word operators (`and`, `is`,
+/// `contains`, ...), it will be evaluated. Anything else is read as a literal value.
+/// Statements compile their arguments once at load time; this parses on every call.
 pub fn resolve_key_or_expression(
     input: &str,
     memory: &MemoryStore,
-) -> Result<String, String> {
-    let trimmed = input.trim_matches('"');
+    options: &EvalOptions,
+) -> Result<Evaluation, String> {
+    CompiledExpr::new(input).evaluate(memory, options)
+}
 
-    let has_math = trimmed.contains('+')
-        || trimmed.contains('-')
-        || trimmed.contains('*')
-        || trimmed.contains('/')
-        || trimmed.contains('%')
-        || trimmed.contains('(')
-        || trimmed.contains(')')
-        || trimmed.contains("{{");
+/// The candidate closest to `key` by edit distance, for "did you mean" hints; ties go to
+/// the alphabetically first candidate
+pub fn nearest_key<'a, I>(key: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != key)
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .min()
+        .map(|(_, candidate)| candidate)
+}
 
-    if has_math {
-        match evaluate_expression(trimmed, &memory.flatten_map()) {
-            Ok(n) => Ok(n.to_string()),
-            Err(e) => Err(format!("Failed to evaluate expression '{}': {}", input, e)),
+/// Levenshtein distance over characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb { diagonal } else { 1 + diagonal.min(above).min(row[j]) };
+            diagonal = above;
         }
-    } else {
-        Ok(trimmed.to_string())
     }
+    row[b.len()]
 }
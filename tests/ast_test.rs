@@ -0,0 +1,221 @@
+use idc_runtime::parser::ast::{build_ast_from_str, AstBuildError, AstNode};
+use idc_runtime::parser::semantic::{analyze_semantics, SemanticWarning};
+use idc_runtime::parser::stmt::{lower, Stmt};
+use idc_runtime::parser::token_kind::TokenKind;
+use idc_runtime::runtime::memory::ScopeTarget;
+
+#[test]
+fn test_nested_blocks_follow_indentation() {
+    let ast = build_ast_from_str("on start\n  if \"1 > 0\"\n    respond \"yes\"\n  respond \"after\"\n")
+        .expect("AST should build");
+    assert_eq!(ast.len(), 1);
+    assert_eq!(ast[0].children.len(), 2);
+    assert_eq!(ast[0].children[0].children.len(), 1);
+}
+
+#[test]
+fn test_rejects_indented_first_line() {
+    let err = build_ast_from_str("  on start\n  respond \"hi\"\n").unwrap_err();
+    assert!(matches!(err, AstBuildError::UnexpectedIndentation(span, _) if span.line == 1));
+}
+
+#[test]
+fn test_rejects_dedent_to_unknown_level() {
+    let source = "on start\n    if \"1 > 0\"\n      respond \"yes\"\n  respond \"orphan\"\n";
+    let err = build_ast_from_str(source).unwrap_err();
+    match err {
+        AstBuildError::UnexpectedIndentation(span, reason) => {
+            assert_eq!(span.line, 4);
+            assert!(reason.contains("column 3"), "unexpected reason: {}", reason);
+        }
+        other => panic!("expected UnexpectedIndentation, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_rejects_mixed_tabs_and_spaces() {
+    let across_lines = "on start\n\tif \"1 > 0\"\n  respond \"yes\"\n";
+    let err = build_ast_from_str(across_lines).unwrap_err();
+    assert!(matches!(err, AstBuildError::MixedIndentation(span) if span.line == 3));
+
+    let within_line = "on start\n \trespond \"yes\"\n";
+    let err = build_ast_from_str(within_line).unwrap_err();
+    assert!(matches!(err, AstBuildError::MixedIndentation(span) if span.line == 2));
+    assert_eq!(err.to_string(), "2:3: indentation mixes tabs and spaces");
+}
+
+#[test]
+fn test_accepts_tab_indentation() {
+    let ast = build_ast_from_str("on start\n\tif \"1 > 0\"\n\t\trespond \"yes\"\n").expect("tabs only");
+    assert_eq!(ast[0].children[0].children.len(), 1);
+}
+
+#[test]
+fn test_lowering_parses_load_once() {
+    let ast = build_ast_from_str(
+        "on start\n  load \"{{counter}} + 1\" as counter to global\n  load total from \"2 * 3\"\n",
+    )
+    .unwrap();
+    let stmts = lower(&ast).expect("script should lower");
+
+    let Stmt::On { event, body } = &stmts[0].node else {
+        panic!("expected an `on` handler, got {:?}", stmts[0].node);
+    };
+    assert_eq!(event, "start");
+
+    match &body[0].node {
+        Stmt::Load { expr, target, scope } => {
+            assert_eq!(expr, "\"{{counter}} + 1\"");
+            assert_eq!(target, "counter");
+            assert_eq!(scope, &Some(ScopeTarget::Global));
+        }
+        other => panic!("expected load, got {:?}", other),
+    }
+    match &body[1].node {
+        Stmt::Load { expr, target, scope } => {
+            assert_eq!(expr, "\"2 * 3\"");
+            assert_eq!(target, "total");
+            assert!(scope.is_none());
+        }
+        other => panic!("expected load, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_lowering_rejects_malformed_statements() {
+    let ast = build_ast_from_str("every soon\n  respond \"tick\"\non start\n  load x as\n  reflect mood\n").unwrap();
+    let errors = lower(&ast).unwrap_err();
+    let lines: Vec<usize> = errors.iter().map(|e| e.span.line).collect();
+    assert_eq!(lines, vec![1, 4, 5]);
+    assert!(errors[1].to_string().starts_with("4:3: "));
+}
+
+#[test]
+fn test_lowering_rejects_body_under_plain_statement() {
+    let ast = build_ast_from_str("on start\n  load 1 as x\n    respond \"never\"\n  respond \"after\"\n").unwrap();
+    let errors = lower(&ast).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "2:3: `load` does not take an indented body");
+
+    let ast = build_ast_from_str("on start\n  stop\n    respond \"never\"\n").unwrap();
+    assert!(lower(&ast).is_err());
+}
+
+#[test]
+fn test_else_attaches_to_preceding_if() {
+    let source = "on start\n  if \"(1 == 2)\"\n    respond \"a\"\n  else if \"(2 == 2)\"\n    respond \"b\"\n  else\n    respond \"c\"\n  respond \"after\"\n";
+    let ast = build_ast_from_str(source).unwrap();
+    let handler = &ast[0];
+    assert_eq!(handler.children.len(), 2, "else branches should not be siblings");
+
+    let first = &handler.children[0];
+    let else_if = first.else_branch.as_deref().expect("else if attached");
+    assert_eq!(else_if.node_type, TokenKind::If);
+    assert_eq!(else_if.value, "\"(2 == 2)\"");
+    let last = else_if.else_branch.as_deref().expect("else attached to else if");
+    assert_eq!(last.node_type, TokenKind::Else);
+    assert_eq!(last.children.len(), 1);
+    assert!(analyze_semantics(&ast).is_empty());
+}
+
+#[test]
+fn test_dangling_else_is_reported() {
+    let source = "on start\n  respond \"a\"\n  else\n    respond \"b\"\n";
+    let ast = build_ast_from_str(source).unwrap();
+
+    let warnings = analyze_semantics(&ast);
+    assert_eq!(warnings.len(), 1);
+    assert!(matches!(warnings[0], SemanticWarning::DanglingElse(span) if span.line == 3));
+
+    let errors = lower(&ast).unwrap_err();
+    assert_eq!(errors[0].span.line, 3);
+}
+
+/// Renders a tree as indented `keyword value` lines, with `else:` marking attached branches
+fn outline(nodes: &[AstNode]) -> Vec<String> {
+    fn walk(node: &AstNode, depth: usize, out: &mut Vec<String>) {
+        out.push(format!("{}{} {}", "  ".repeat(depth), node.node_type, node.value).trim_end().to_string());
+        for child in &node.children {
+            walk(child, depth + 1, out);
+        }
+        if let Some(branch) = &node.else_branch {
+            out.push(format!("{}else:", "  ".repeat(depth)));
+            walk(branch, depth + 1, out);
+        }
+    }
+    let mut out = Vec::new();
+    for node in nodes {
+        walk(node, 0, &mut out);
+    }
+    out
+}
+
+#[test]
+fn test_arrow_chain_is_sequential() {
+    let ast = build_ast_from_str("on start\n  load user → verify token -> respond user\n").unwrap();
+    assert_eq!(outline(&ast), ["on start", "  load user", "  verify token", "  respond user"]);
+}
+
+#[test]
+fn test_comma_clause_becomes_body() {
+    let ast = build_ast_from_str(
+        "on login, verify token → if invalid, respond \"Invalid, token\" with status 401\n",
+    )
+    .unwrap();
+    assert_eq!(
+        outline(&ast),
+        ["on login", "  verify token", "  if invalid", "    respond \"Invalid, token\""]
+    );
+    assert_eq!(ast[0].children[0].span.column, 11);
+}
+
+#[test]
+fn test_inline_else_closes_conditional() {
+    let ast = build_ast_from_str("on start\n  check login → if failed, retry once → else continue\n").unwrap();
+    assert_eq!(
+        outline(&ast),
+        ["on start", "  check login", "  if failed", "    retry once", "  else:", "    else", "      continue"]
+    );
+
+    let ast = build_ast_from_str("if a, respond a → else if b, respond b → else respond c\n").unwrap();
+    assert_eq!(
+        outline(&ast),
+        ["if a", "  respond a", "else:", "  if b", "    respond b", "  else:", "    else", "      respond c"]
+    );
+}
+
+#[test]
+fn test_trailing_conditional_takes_indented_body() {
+    let source = "on signup\n  check email → if invalid\n    respond \"Invalid email\"\n  else\n    respond \"Success\"\n";
+    let ast = build_ast_from_str(source).unwrap();
+    assert_eq!(
+        outline(&ast),
+        [
+            "on signup",
+            "  check email",
+            "  if invalid",
+            "    respond \"Invalid email\"",
+            "  else:",
+            "    else",
+            "      respond \"Success\"",
+        ]
+    );
+}
+
+#[test]
+fn test_indented_block_attaches_to_open_block_head() {
+    let ast = build_ast_from_str("on start, load 1 as x\n  respond x\n  respond \"done\"\n").unwrap();
+    assert_eq!(
+        outline(&ast),
+        ["on start", "  load 1", "  respond x", "  respond \"done\""]
+    );
+    assert!(ast[0].children[0].children.is_empty());
+}
+
+#[test]
+fn test_indented_block_without_open_block_head_is_rejected() {
+    let err = build_ast_from_str("on start\n  load 1 as x → respond x\n    respond \"lost\"\n").unwrap_err();
+    assert!(matches!(err, AstBuildError::UnexpectedIndentation(..)));
+    assert_eq!(err.span().map(|span| span.line), Some(3));
+    assert!(err.to_string().contains("leaves no block open"), "{}", err);
+}
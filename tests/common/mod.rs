@@ -0,0 +1,8 @@
+use idc_runtime::interpreter::engine::Runtime;
+
+/// Loads and runs a script, returning everything it responded with
+pub fn run(source: &str) -> Vec<String> {
+    let mut runtime = Runtime::from_source(source, false).expect("script should load");
+    runtime.run();
+    runtime.responses().to_vec()
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use common::run;
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::interpreter::math::{evaluate_expression, evaluate_with, CompiledExpr, EvalOptions, OverflowPolicy, Template};
+use idc_runtime::runtime::value::Value;
+use std::collections::HashMap;
+
+fn eval(expr: &str) -> Result<Value, String> {
+    let mut memory = HashMap::new();
+    memory.insert("count".to_string(), Value::Int(3));
+    memory.insert("status".to_string(), Value::from("ready"));
+    memory.insert("user".to_string(), Value::from_literal(r#"{"id":7,"roles":["admin"]}"#));
+    evaluate_expression(expr, &memory)
+}
+
+#[test]
+fn test_unary_operators() {
+    assert_eq!(eval("-3 + 1"), Ok(Value::Int(-2)));
+    assert_eq!(eval("-(2 * 3)"), Ok(Value::Int(-6)));
+    assert_eq!(eval("2 * -{{count}}"), Ok(Value::Int(-6)));
+    assert_eq!(eval("- -4"), Ok(Value::Int(4)));
+    assert_eq!(eval("-1.5"), Ok(Value::Float(-1.5)));
+    assert_eq!(eval("!true"), Ok(Value::Bool(false)));
+    assert_eq!(eval("!0 == false"), Ok(Value::Bool(false)), "! binds tighter than ==");
+}
+
+#[test]
+fn test_logical_connectives_and_precedence() {
+    assert_eq!(eval("1 < 2 && 2 < 3"), Ok(Value::Bool(true)));
+    assert_eq!(eval("1 > 2 || 2 > 3"), Ok(Value::Bool(false)));
+    assert_eq!(eval("true or false and false"), Ok(Value::Bool(true)), "and binds tighter than or");
+    assert_eq!(eval("not 1 == 2"), Ok(Value::Bool(true)), "not applies to the whole comparison");
+    assert_eq!(eval("not count > 2 or count == 3"), Ok(Value::Bool(true)));
+    assert_eq!(eval("1 + 2 * 3 == 7"), Ok(Value::Bool(true)));
+}
+
+#[test]
+fn test_short_circuit_skips_failing_side() {
+    assert_eq!(eval("false and 1 / 0"), Ok(Value::Bool(false)));
+    assert_eq!(eval("true or 1 / 0"), Ok(Value::Bool(true)));
+    assert!(eval("true and 1 / 0").is_err());
+}
+
+#[test]
+fn test_word_operators() {
+    assert_eq!(eval("status is ready"), Ok(Value::Bool(true)));
+    assert_eq!(eval("{{status}} is not done"), Ok(Value::Bool(true)));
+    assert_eq!(eval("count is 3"), Ok(Value::Bool(true)));
+    assert_eq!(eval("user.id is missing"), Ok(Value::Bool(false)));
+    assert_eq!(eval("user.email is missing"), Ok(Value::Bool(true)));
+    assert_eq!(eval("user.email is not missing"), Ok(Value::Bool(false)));
+    assert_eq!(eval("user has roles"), Ok(Value::Bool(true)));
+    assert_eq!(eval("user has email"), Ok(Value::Bool(false)));
+    assert_eq!(eval("user.roles has admin"), Ok(Value::Bool(true)));
+    assert_eq!(eval("user.roles[0] is admin and count > 1"), Ok(Value::Bool(true)));
+}
+
+#[test]
+fn test_errors_point_at_position() {
+    assert_eq!(eval("1 + $"), Err("Math error: unexpected character '$' at position 5".to_string()));
+    assert_eq!(eval("1 +"), Err("Math error: expected a value at position 4".to_string()));
+    assert_eq!(eval("(1 + 2"), Err("Math error: expected ')' to close '(' from position 1 at position 7".to_string()));
+    assert_eq!(eval("1 2"), Err("Math error: unexpected value '2' at position 3".to_string()));
+    assert_eq!(eval("and true"), Err("Math error: expected a value before 'and' at position 1".to_string()));
+    assert_eq!(eval("-status"), Err("Math error: cannot negate text 'ready'".to_string()));
+}
+
+#[test]
+fn test_natural_conditions_in_scripts() {
+    let responses = run(
+        "on start\n  load {\"id\":7} as user\n  if user.email is missing\n    respond \"no email\"\n  if user.id is 7 and not user.id is missing\n    respond \"seven\"\n  unless user has id\n    respond \"unreachable\"\n",
+    );
+    assert_eq!(responses, ["\"no email\"", "\"seven\""]);
+}
+
+fn eval_with_policy(expr: &str, overflow: OverflowPolicy) -> Result<(Value, usize), String> {
+    let options = EvalOptions { overflow, ..EvalOptions::default() };
+    evaluate_with(expr, &HashMap::new(), &options).map(|e| (e.value, e.warnings.len()))
+}
+
+#[test]
+fn test_overflow_policies() {
+    let max = "9223372036854775807 + 1";
+    assert!(eval_with_policy(max, OverflowPolicy::Error).unwrap_err().contains("integer overflow"));
+    assert_eq!(eval_with_policy(max, OverflowPolicy::Saturate), Ok((Value::Int(i64::MAX), 1)));
+    assert_eq!(eval_with_policy(max, OverflowPolicy::Wrap), Ok((Value::Int(i64::MIN), 1)));
+    assert_eq!(eval_with_policy(max, OverflowPolicy::Promote), Ok((Value::BigInt(9223372036854775808), 1)));
+    assert_eq!(eval_with_policy("-9223372036854775807 - 2", OverflowPolicy::Saturate), Ok((Value::Int(i64::MIN), 1)));
+    assert_eq!(eval_with_policy("2 + 2", OverflowPolicy::Wrap), Ok((Value::Int(4), 0)), "no warning without overflow");
+}
+
+#[test]
+fn test_overflow_policy_setting() {
+    let script = "set overflow saturate\non start\n  respond_event \"9223372036854775807 * 2\"\n";
+    assert_eq!(run(script), ["9223372036854775807"]);
+    let script = "set overflow wrap\non start\n  respond \"{{-9223372036854775807 - 3}}\"\n";
+    assert_eq!(run(script), ["\"9223372036854775806\""]);
+    let script = "on start\n  respond_event \"9223372036854775807 * 2\"\n  respond \"after\"\n";
+    assert_eq!(run(script), ["\"after\""], "the default policy reports the error and carries on");
+    assert!(Runtime::from_source("set overflow bigint\n", false).is_err());
+}
+
+#[test]
+fn test_promoted_integers_stay_exact() {
+    let promote = |expr| eval_with_policy(expr, OverflowPolicy::Promote).map(|(value, _)| value);
+    assert_eq!(promote("9223372036854775807 * 4 + 1"), Ok(Value::BigInt(36893488147419103229)));
+    assert_eq!(promote("(9223372036854775807 + 10) - 20"), Ok(Value::Int(9223372036854775797)));
+    assert_eq!(promote("-(-9223372036854775807 - 1)"), Ok(Value::BigInt(9223372036854775808)));
+    assert_eq!(promote("9223372036854775807 + 1 > 9223372036854775807"), Ok(Value::Bool(true)));
+    assert_eq!(promote("9223372036854775807 * 2 == 18446744073709551614"), Ok(Value::Bool(true)));
+    assert!(promote("9223372036854775807 * 9223372036854775807 * 9223372036854775807").unwrap_err().contains("exceeds 128 bits"));
+
+    let big = Value::BigInt(18446744073709551614);
+    assert_eq!(big.type_name(), "int");
+    assert_eq!(serde_json::to_string(&big).unwrap(), "18446744073709551614");
+    assert_eq!(serde_json::from_str::<Value>("18446744073709551614").unwrap(), big);
+    assert_eq!(Value::from_literal("18446744073709551614"), big);
+    let script = "set overflow promote\non start\n  respond_event \"9223372036854775807 * 2\"\n";
+    assert_eq!(run(script), ["18446744073709551614"]);
+}
+
+#[test]
+fn test_string_literals_and_operators() {
+    assert_eq!(eval("'ready' == status"), Ok(Value::Bool(true)));
+    assert_eq!(eval(r#""a \"quoted\" word""#), Ok(Value::from(r#"a "quoted" word"#)));
+    assert_eq!(eval("'apple' < 'banana'"), Ok(Value::Bool(true)));
+    assert_eq!(eval("status >= 'zebra'"), Ok(Value::Bool(false)));
+    assert_eq!(eval("'count: ' + count"), Ok(Value::from("count: 3")));
+    assert_eq!(eval("status + '!' + 1 + 1"), Ok(Value::from("ready!11")));
+    assert_eq!(eval("status contains 'ea'"), Ok(Value::Bool(true)));
+    assert_eq!(eval("status starts with 're' and status ends with 'dy'"), Ok(Value::Bool(true)));
+    assert_eq!(eval("user.roles contains 'admin'"), Ok(Value::Bool(true)));
+    assert_eq!(eval("length of status"), Ok(Value::Int(5)));
+    assert_eq!(eval("length user.roles + 1"), Ok(Value::Int(2)));
+    assert_eq!(eval("length 'héllo' == 5"), Ok(Value::Bool(true)), "length counts characters");
+    assert_eq!(eval("'open"), Err("Math error: unterminated text starting at position 1".to_string()));
+    assert!(eval("status starts 're'").is_err());
+    assert!(eval("length count").is_err());
+}
+
+#[test]
+fn test_string_conditions_in_scripts() {
+    let responses = run(
+        "on start\n  load \"ready\" as status\n  if \"{{status}} == ready\"\n    respond \"placeholder\"\n  if status == \"ready\"\n    respond \"quoted\"\n  unless status starts with \"x\"\n    respond \"prefix\"\n  load \"{{status}} + ' to go'\" as phrase\n  respond \"{{phrase}}\"\n  load \"length of phrase\" as size\n  respond_event \"{{size}}\"\n",
+    );
+    assert_eq!(responses, ["\"placeholder\"", "\"quoted\"", "\"prefix\"", "\"ready to go\"", "11"]);
+}
+
+#[test]
+fn test_compiled_expressions_evaluate_repeatedly() {
+    let options = EvalOptions::default();
+    let mut memory = HashMap::new();
+    let compiled = CompiledExpr::new("\"{{count}} * 2 + 1\"");
+    for count in 0..3 {
+        memory.insert("count".to_string(), Value::Int(count));
+        assert_eq!(compiled.evaluate(&memory, &options).map(|e| e.value), Ok(Value::Int(count * 2 + 1)));
+    }
+    assert_eq!(compiled.source(), "\"{{count}} * 2 + 1\"");
+
+    let literal = CompiledExpr::new("\"hello world\"");
+    assert_eq!(literal.evaluate(&memory, &options).map(|e| e.value), Ok(Value::from("hello world")));
+
+    let invalid = CompiledExpr::new("1 + $");
+    assert_eq!(
+        invalid.evaluate(&memory, &options).map(|e| e.value),
+        Err("Failed to evaluate expression '1 + $': Math error: unexpected character '$' at position 5".to_string())
+    );
+
+    let template = Template::new("{{count}} of {{count + 1}}, {{1 +}}");
+    assert_eq!(template.render(&memory, &options).unwrap().text, "2 of 3, 0");
+}
+
+#[test]
+fn test_strict_mode_names_the_nearest_key() {
+    let mut memory = HashMap::new();
+    memory.insert("counter".to_string(), Value::Int(3));
+    memory.insert("color".to_string(), Value::from("red"));
+    let strict = EvalOptions { strict: true, ..EvalOptions::default() };
+
+    assert_eq!(
+        evaluate_with("{{conter}} + 1", &memory, &strict).map(|e| e.value),
+        Err("Math error: unresolved placeholder '{{conter}}'; nearest key is 'counter'".to_string())
+    );
+    assert_eq!(
+        Template::new("n={{conter}}").render(&memory, &strict).map(|r| r.text),
+        Err("Math error: unresolved placeholder '{{conter}}'; nearest key is 'counter'".to_string())
+    );
+    assert!(Template::new("{{1 +}}").render(&memory, &strict).unwrap_err().contains("placeholder '{{1 +}}' failed"));
+    assert_eq!(evaluate_with("{{counter}} + 1", &memory, &strict).map(|e| e.value), Ok(Value::Int(4)));
+}
+
+#[test]
+fn test_lenient_mode_records_substitutions() {
+    let mut memory = HashMap::new();
+    memory.insert("counter".to_string(), Value::Int(3));
+    let options = EvalOptions::default();
+
+    let evaluation = evaluate_with("{{conter}} + 1", &memory, &options).unwrap();
+    assert_eq!(evaluation.value, Value::Int(1));
+    assert_eq!(evaluation.substitutions, ["'{{conter}}' is unresolved; nearest key is 'counter'; substituted 0"]);
+
+    let rendered = Template::new("{{conter}} and {{1 +}}").render(&memory, &options).unwrap();
+    assert_eq!(rendered.text, "0 and 0");
+    assert_eq!(rendered.substitutions.len(), 2);
+    assert!(evaluate_with("{{counter}}", &memory, &options).unwrap().substitutions.is_empty());
+}
+
+#[test]
+fn test_strict_setting_in_scripts() {
+    let script = "set strict true\non start\n  load 3 as counter\n  respond \"count {{conter}}\"\n  respond \"count {{counter}}\"\n";
+    assert_eq!(run(script), ["\"count 3\""], "the unresolved response is not sent");
+
+    let mut runtime = Runtime::from_source("on start\n  respond \"{{missing}}\"\n", false).unwrap();
+    runtime.set_strict(true);
+    runtime.run();
+    assert!(runtime.responses().is_empty());
+
+    assert_eq!(run("on start\n  respond \"{{missing}}\"\n"), ["\"0\""]);
+    assert!(Runtime::from_source("set strict maybe\n", false).is_err());
+}
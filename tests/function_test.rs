@@ -0,0 +1,90 @@
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::interpreter::functions::FunctionRegistry;
+use idc_runtime::interpreter::math::{evaluate_expression, evaluate_with, EvalOptions};
+use idc_runtime::runtime::value::Value;
+use std::collections::HashMap;
+
+fn eval(expr: &str) -> Result<Value, String> {
+    let mut memory = HashMap::new();
+    memory.insert("count".to_string(), Value::Int(3));
+    memory.insert("name".to_string(), Value::from("Ada"));
+    memory.insert("roles".to_string(), Value::from_literal(r#"["admin","dev"]"#));
+    evaluate_expression(expr, &memory)
+}
+
+fn run(runtime: &mut Runtime) -> Vec<String> {
+    runtime.run();
+    runtime.responses().to_vec()
+}
+
+#[test]
+fn test_builtin_functions() {
+    assert_eq!(eval("min(4, count, 7)"), Ok(Value::Int(3)));
+    assert_eq!(eval("max(1, 2.5)"), Ok(Value::Float(2.5)));
+    assert_eq!(eval("max('pear', 'apple')"), Ok(Value::from("pear")));
+    assert_eq!(eval("abs(-count)"), Ok(Value::Int(3)));
+    assert_eq!(eval("abs(-1.5)"), Ok(Value::Float(1.5)));
+    assert_eq!(eval("clamp(count * 10, 0, 25)"), Ok(Value::Int(25)));
+    assert_eq!(eval("clamp(-1, 0, 25)"), Ok(Value::Int(0)));
+    assert_eq!(eval("len(roles) + len(name)"), Ok(Value::Int(5)));
+    assert_eq!(eval("round(2.5)"), Ok(Value::Int(3)));
+    assert_eq!(eval("round(2.71828, 2)"), Ok(Value::Float(2.72)));
+    assert_eq!(eval("upper(name) + lower('!X')"), Ok(Value::from("ADA!x")));
+    assert!(matches!(eval("now()"), Ok(Value::Int(secs)) if secs > 1_600_000_000));
+}
+
+#[test]
+fn test_function_call_errors() {
+    assert_eq!(eval("nope(1)"), Err("Math error: unknown function 'nope' at position 1".to_string()));
+    assert_eq!(eval("abs(1, 2)"), Err("Math error: abs() expects 1 argument, got 2".to_string()));
+    assert_eq!(eval("min()"), Err("Math error: min() expects at least 1 argument, got 0".to_string()));
+    assert_eq!(eval("upper(count)"), Err("Math error: upper() expects text, got int '3'".to_string()));
+    assert_eq!(
+        eval("max(1 2)"),
+        Err("Math error: expected ',' or ')' in call to 'max' from position 1 at position 7".to_string())
+    );
+    assert!(eval("clamp(1, 5, 0)").is_err());
+}
+
+#[test]
+fn test_round_rejects_values_without_an_integer() {
+    assert_eq!(
+        eval("round(10000000000000000000.0)"),
+        Err("Math error: round() 10000000000000000000.0 does not fit in an integer".to_string())
+    );
+    assert_eq!(eval("round(-9223372036854775808.0)"), Ok(Value::Int(i64::MIN)));
+    let round = FunctionRegistry::with_builtins().get("round").cloned().unwrap();
+    assert!(round(&[Value::Float(f64::NAN)]).is_err());
+    assert!(round(&[Value::Float(f64::INFINITY)]).is_err());
+    assert_eq!(round(&[Value::Float(f64::NAN), Value::Int(2)]).map(|v| v.to_string()), Ok("NaN".to_string()));
+}
+
+#[test]
+fn test_empty_registry_has_no_functions() {
+    let options = EvalOptions { functions: FunctionRegistry::empty(), ..EvalOptions::default() };
+    assert!(evaluate_with("max(1, 2)", &HashMap::new(), &options).is_err());
+    assert!(FunctionRegistry::with_builtins().names().contains(&"clamp"));
+}
+
+#[test]
+fn test_functions_in_scripts() {
+    let mut runtime = Runtime::from_source(
+        "on start\n  load \"clamp(150, 0, 100)\" as pct\n  if max({{pct}}, 10) == 100, respond \"capped\"\n  respond \"{{upper('done')}}\"\n",
+        false,
+    )
+    .expect("script should load");
+    assert_eq!(run(&mut runtime), ["\"capped\"", "\"DONE\""]);
+}
+
+#[test]
+fn test_host_registered_function() {
+    let mut runtime =
+        Runtime::from_source("on start\n  respond_event \"double(21)\"\n  respond_event \"abs(-2)\"\n", false)
+            .expect("script should load");
+    runtime.register_function("double", |args| match args {
+        [Value::Int(n)] => Ok(Value::Int(n * 2)),
+        _ => Err("expects one int".to_string()),
+    });
+    runtime.register_function("abs", |_| Ok(Value::from("overridden")));
+    assert_eq!(run(&mut runtime), ["42", "overridden"]);
+}
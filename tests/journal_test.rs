@@ -0,0 +1,161 @@
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::interpreter::journal::{Outcome, SkipCause};
+use idc_runtime::runtime::value::Value;
+
+fn run(source: &str) -> Runtime {
+    let mut runtime = Runtime::from_source(source, false).expect("script should load");
+    runtime.run();
+    runtime
+}
+
+#[test]
+fn test_why_skipped_by_condition() {
+    let runtime = run(
+        "on start\n  load 0 as logged_in\n  if \"({{logged_in}} == 1)\"\n    respond \"Welcome\"\n",
+    );
+    let answer = runtime.query("why: did it skip \"respond Welcome\"").unwrap();
+    assert_eq!(
+        answer,
+        "Skipped `respond \"Welcome\"` at 4:5 because `if \"({{logged_in}} == 1)\"` at 3:3 evaluated to false."
+    );
+}
+
+#[test]
+fn test_why_skipped_by_stop() {
+    let runtime = run("on start\n  respond \"a\"\n  stop\n  respond \"Welcome\"\n");
+    let answer = runtime.query("why: did it skip \"Welcome\"").unwrap();
+    assert_eq!(answer, "Skipped `respond \"Welcome\"` at 4:3 because `stop` at 3:3 left the block first.");
+
+    let skipped = runtime
+        .journal()
+        .entries()
+        .find(|e| matches!(e.outcome, Outcome::Skipped(_)))
+        .expect("skipped entry");
+    assert!(matches!(&skipped.outcome, Outcome::Skipped(SkipCause::Unwound { keyword, .. }) if keyword == "stop"));
+    assert_eq!(skipped.scope_chain, "global > event:start");
+}
+
+#[test]
+fn test_why_skip_for_statement_that_ran() {
+    let runtime = run("on start\n  respond \"Welcome\"\n");
+    let answer = runtime.query("why: did it skip \"respond Welcome\"").unwrap();
+    assert_eq!(answer, "It did not skip `respond \"Welcome\"`: it ran at 2:3 in [global > event:start].");
+}
+
+#[test]
+fn test_why_respond_and_explain_last_response() {
+    let runtime = run(
+        "on start\n  if \"(1 == 2)\"\n    respond \"no\"\n  else\n    respond \"Please log in\"\n  respond_event \"2 + 3\"\n",
+    );
+    assert_eq!(
+        runtime.query("why: did it respond \"Please log in\"").unwrap(),
+        "Responded \"Please log in\" from `respond \"Please log in\"` at 5:5 in [global > event:start] \
+         because `if \"(1 == 2)\"` at 2:3 evaluated to false so its `else` ran."
+    );
+    assert_eq!(
+        runtime.query("explain: last response").unwrap(),
+        "Responded 5 from `respond_event \"2 + 3\"` at 6:3 in [global > event:start]."
+    );
+    assert!(runtime.query("why: did it respond \"no\"").unwrap().starts_with("It did not respond \"no\""));
+}
+
+#[test]
+fn test_answer_locations_name_the_script() {
+    let mut runtime = Runtime::from_source(
+        "on start\n  if \"(1 == 2)\"\n    respond \"no\"\n  else\n    respond \"yes\"\n",
+        false,
+    )
+    .unwrap();
+    runtime.set_source_name("app.idc");
+    runtime.run();
+    assert_eq!(
+        runtime.query("why: did it skip \"respond no\"").unwrap(),
+        "Skipped `respond \"no\"` at app.idc:3:5 because `if \"(1 == 2)\"` at app.idc:2:3 evaluated to false."
+    );
+    assert_eq!(
+        runtime.query("why: did it respond \"yes\"").unwrap(),
+        "Responded \"yes\" from `respond \"yes\"` at app.idc:5:5 in [global > event:start] \
+         because `if \"(1 == 2)\"` at app.idc:2:3 evaluated to false so its `else` ran."
+    );
+}
+
+#[test]
+fn test_query_statement_in_script() {
+    let runtime = run("on start\n  if \"(0)\"\n    respond \"Welcome\"\n  why: did it skip \"respond Welcome\"\n");
+    assert_eq!(
+        runtime.responses(),
+        ["Skipped `respond \"Welcome\"` at 3:5 because `if \"(0)\"` at 2:3 evaluated to 0."]
+    );
+}
+
+#[test]
+fn test_malformed_query_is_rejected() {
+    assert!(Runtime::from_source("on start\n  why: did it vanish \"x\"\n", false).is_err());
+    assert!(Runtime::from_source("on start\n  explain: everything\n", false).is_err());
+    assert!(Runtime::new(false).query("what happened").is_err());
+}
+
+#[test]
+fn test_trace_logic_after_node() {
+    let runtime = run(
+        "on start\n  verify token\n  load 1 as ok\n  if \"({{ok}} == 0)\"\n    respond \"bad\"\n  respond \"done\"\n",
+    );
+    assert_eq!(
+        runtime.query("trace: logic after verify token").unwrap(),
+        "After `verify token` at 2:3:\n\
+         \x20 1. `load 1 as ok` at 3:3 in [global > event:start]\n\
+         \x20 2. `if \"({{ok}} == 0)\"` at 4:3 in [global > event:start]\n\
+         \x20 3. `respond \"done\"` at 6:3 in [global > event:start]"
+    );
+    assert_eq!(
+        runtime.query("trace: after respond done").unwrap(),
+        "After `respond \"done\"` at 6:3:\n  nothing else ran"
+    );
+}
+
+#[test]
+fn test_trace_memory_key_lineage() {
+    let mut runtime = Runtime::from_source(
+        "on start\n  load 1 as count to global\n  trigger bump with count 5\n\non bump\n  load 6 as count\n\nevery 2\n  load 9 as count to global\n",
+        false,
+    )
+    .unwrap();
+    runtime.set_promote_always(false);
+    runtime.run();
+    runtime.tick();
+    runtime.tick();
+
+    let expected = "Writes to \"count\":\n\
+                    \x20 1. count = 1 in [global] at tick 0 from 2:3\n\
+                    \x20 2. count = 5 in [event:bump] at tick 0 from 3:3\n\
+                    \x20 3. count = 6 in [event:bump] at tick 0 from 6:3\n\
+                    \x20 4. count = 9 in [global] at tick 2 from 9:3";
+    assert_eq!(runtime.query("trace: memory \"count\"").unwrap(), expected);
+    assert_eq!(runtime.query("trace: count").unwrap(), expected);
+    assert_eq!(runtime.query("trace: memory missing_key").unwrap(), "Nothing has written \"missing_key\".");
+}
+
+#[test]
+fn test_trace_host_trigger_payload() {
+    let mut runtime = Runtime::from_source("on ping\n  respond \"pong\"\n", false).unwrap();
+    runtime.trigger_event_with("ping", &[("id".to_string(), Value::Int(3))]);
+    assert_eq!(
+        runtime.query("trace: memory id").unwrap(),
+        "Writes to \"id\":\n  1. id = 3 in [event:ping] at tick 0 from the host"
+    );
+}
+
+#[test]
+fn test_journaling_can_be_turned_off() {
+    let mut runtime = Runtime::from_source("on start\n  respond \"a\"\n  stop\n  respond \"b\"\n", false).unwrap();
+    runtime.set_journaling(false);
+    runtime.set_tick_banner(false);
+    runtime.run();
+    runtime.tick();
+    assert_eq!(runtime.responses(), ["\"a\""]);
+    assert_eq!(runtime.journal().entries().count(), 0);
+
+    runtime.set_journaling(true);
+    runtime.run();
+    assert_eq!(runtime.journal().entries().count(), 3);
+}
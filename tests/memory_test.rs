@@ -0,0 +1,172 @@
+mod common;
+
+use common::run;
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::interpreter::math::{CompiledExpr, EvalOptions, Template};
+use idc_runtime::runtime::memory::{MemoryStore, ScopeError, ScopeTarget, WriteError};
+use idc_runtime::runtime::path::{ParsedKey, Path, Segment};
+use idc_runtime::runtime::value::Value;
+
+#[test]
+fn test_path_parsing() {
+    let path = Path::parse("user.roles[0].name").unwrap();
+    assert_eq!(path.root, "user");
+    assert_eq!(
+        path.rest,
+        [Segment::Key("roles".into()), Segment::Index(0), Segment::Key("name".into())]
+    );
+    assert!(!Path::parse("count").unwrap().is_nested());
+    assert!(Path::parse("user..id").is_none());
+    assert!(Path::parse("roles[x]").is_none());
+    assert!(Path::parse("1 + 2").is_none());
+}
+
+#[test]
+fn test_nested_set_and_get() {
+    let mut memory = MemoryStore::new();
+    memory.set("user.id", Value::Int(7), None);
+    memory.set("user.roles", Value::from_literal(r#"["admin","dev"]"#), None);
+    memory.set("user.roles[2]", Value::from("ops"), None);
+
+    assert_eq!(memory.get("user.id"), Some(Value::Int(7)));
+    assert_eq!(memory.get("user.roles[1]"), Some(Value::from("dev")));
+    assert_eq!(memory.get("user.roles[2]"), Some(Value::from("ops")));
+    assert_eq!(memory.get("user.roles[3]"), None);
+    assert_eq!(memory.get("user.name"), None);
+    assert_eq!(
+        serde_json::to_string(&memory.flatten_map()).unwrap(),
+        r#"{"user":{"id":7,"roles":["admin","dev","ops"]}}"#
+    );
+
+    // Writing through a scalar is refused and leaves memory unchanged
+    memory.set("user.id.value", Value::Int(1), None);
+    assert_eq!(memory.get("user.id"), Some(Value::Int(7)));
+    memory.set("user.roles[9]", Value::Int(1), None);
+    assert_eq!(memory.get("user.roles[9]"), None);
+}
+
+#[test]
+fn test_compiled_keys_keep_their_parsed_path() {
+    assert_eq!(ParsedKey::new("count").path, None);
+    assert_eq!(ParsedKey::new("user.roles[1]").path, Path::parse("user.roles[1]"));
+    assert_eq!(ParsedKey::new("user..id").path, None);
+
+    let mut memory = MemoryStore::new();
+    memory.set("user.roles", Value::from_literal(r#"["admin","dev"]"#), None);
+    memory.enter_scope("event:start", None);
+    memory.set("count", Value::Int(2), None);
+    let options = EvalOptions::default();
+    let value = CompiledExpr::new("user.roles[1] == 'dev' and count > 1").evaluate(&memory, &options);
+    assert_eq!(value.unwrap().value, Value::Bool(true));
+    let rendered = Template::new("{{user.roles[0]}} x{{count}}").render(&memory, &options);
+    assert_eq!(rendered.unwrap().text, "admin x2");
+}
+
+#[test]
+fn test_nested_write_shadows_outer_root_whole() {
+    let mut memory = MemoryStore::new();
+    memory.set("user.id", Value::Int(7), None);
+    memory.set("user.name", Value::from("Ada"), None);
+
+    memory.enter_scope("event:login", None);
+    memory.set("user.id", Value::Int(8), None);
+    assert_eq!(memory.get("user.id"), Some(Value::Int(8)));
+    assert_eq!(memory.get("user.name"), Some(Value::from("Ada")));
+    memory.exit_scope(None);
+
+    assert_eq!(memory.get("user.id"), Some(Value::Int(7)));
+}
+
+#[test]
+fn test_paths_in_scripts() {
+    let responses = run(
+        "on start\n  load {\"id\":41,\"roles\":[\"admin\"]} as user\n  load \"{{user.id}} + 1\" as user.id\n  load guest as user.roles[1]\n  respond \"{{user.id}} {{user.roles[0]}} {{user.roles[1]}}\"\n  if \"({{user.id}} == 42)\"\n    respond_event \"{{user.roles}}\"\n",
+    );
+    assert_eq!(responses, ["\"42 admin guest\"", "[\"admin\",\"guest\"]"]);
+}
+
+#[test]
+fn test_payload_keys_can_be_paths() {
+    let mut runtime = Runtime::from_source("on login\n  respond \"{{query.id}}\"\n", false).unwrap();
+    runtime.trigger_event_with("login", &[("query.id".to_string(), Value::Int(5))]);
+    assert_eq!(runtime.responses(), ["\"5\""]);
+}
+
+#[test]
+fn test_scope_target_parsing() {
+    assert_eq!("local".parse(), Ok(ScopeTarget::Local));
+    assert_eq!("parent".parse(), Ok(ScopeTarget::Outer(1)));
+    assert_eq!("outer 2".parse(), Ok(ScopeTarget::Outer(2)));
+    assert_eq!("event:user login".parse(), Ok(ScopeTarget::Named("event:user login".into())));
+    assert!("outer 0".parse::<ScopeTarget>().is_err());
+    assert!("outer two".parse::<ScopeTarget>().is_err());
+    assert!("far away".parse::<ScopeTarget>().is_err());
+    assert_eq!(ScopeTarget::Outer(1).to_string(), "parent");
+}
+
+#[test]
+fn test_set_target_by_name_and_position() {
+    let mut memory = MemoryStore::new();
+    memory.enter_scope("event:start", None);
+    memory.enter_scope("event:login", None);
+    memory.enter_scope("tick:1", None);
+
+    memory.set_target("a", Value::Int(1), Some(&ScopeTarget::Outer(1)), None).unwrap();
+    memory.set_target("b", Value::Int(2), Some(&ScopeTarget::Outer(3)), None).unwrap();
+    memory.set_target("c", Value::Int(3), Some(&ScopeTarget::Named("event:start".into())), None).unwrap();
+    memory.set_target("d", Value::Int(4), None, None).unwrap();
+
+    let scopes = memory.all_scopes();
+    assert_eq!(scopes[2].1.get("a"), Some(&Value::Int(1)));
+    assert_eq!(scopes[0].1.get("b"), Some(&Value::Int(2)));
+    assert_eq!(scopes[1].1.get("c"), Some(&Value::Int(3)));
+    assert_eq!(scopes[3].1.get("d"), Some(&Value::Int(4)));
+
+    let missing = memory.set_target("e", Value::Int(5), Some(&ScopeTarget::Named("event:logout".into())), None);
+    assert_eq!(
+        missing,
+        Err(WriteError::Scope(ScopeError::NotOnStack {
+            name: "event:logout".into(),
+            chain: "global > event:start > event:login > tick:1".into(),
+        }))
+    );
+    assert!(matches!(
+        memory.set_target("e", Value::Int(5), Some(&ScopeTarget::Outer(4)), None),
+        Err(WriteError::Scope(ScopeError::TooFar { levels: 4, .. }))
+    ));
+    assert_eq!(memory.get("e"), None);
+}
+
+#[test]
+fn test_load_to_named_and_relative_scopes() {
+    let responses = run(
+        "on inner\n  load 1 as from_parent to parent\n  load 2 as from_start to event:start\n  load 3 as from_outer to outer 2\non start\n  trigger inner\n  respond \"{{from_parent}} {{from_start}} {{from_outer}}\"\n",
+    );
+    assert_eq!(responses, ["\"1 2 3\""]);
+}
+
+#[test]
+fn test_load_to_missing_scope_is_a_failure() {
+    let mut runtime = Runtime::from_source(
+        "on start\n  load 1 as kept to global\n  atomic\n    load 2 as kept to global\n    load 3 as lost to event:logout\n  respond \"{{kept}} {{lost}}\"\n",
+        false,
+    )
+    .unwrap();
+    runtime.run();
+    assert_eq!(runtime.responses(), ["\"1 0\""]);
+    assert!(Runtime::from_source("on start\n  load 1 as x to outer zero\n", false).is_err());
+}
+
+#[test]
+fn test_flatten_map_lets_inner_scopes_shadow_outer_ones() {
+    let mut memory = MemoryStore::new();
+    memory.set("mode", Value::from("global"), None);
+    memory.set("only_global", Value::Int(1), None);
+    memory.enter_scope("event:start", None);
+    memory.set("mode", Value::from("handler"), None);
+
+    let flat = memory.flatten_map();
+    assert_eq!(flat.get("mode"), Some(&Value::from("handler")));
+    assert_eq!(flat.get("only_global"), Some(&Value::Int(1)));
+    assert_eq!(flat.get("mode").cloned(), memory.get("mode"), "flattening agrees with lookup");
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::run;
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::runtime::memory::MemoryStore;
+use idc_runtime::runtime::promotion::{self, MergeStrategy, Outcome, PromotionPolicy};
+use idc_runtime::runtime::value::Value;
+
+#[test]
+fn test_key_patterns() {
+    assert!(promotion::matches("score", "score"));
+    assert!(promotion::matches("tmp_*", "tmp_"));
+    assert!(promotion::matches("tmp_*", "tmp_total"));
+    assert!(promotion::matches("*_count", "login_count"));
+    assert!(promotion::matches("a*b*c", "axxbyyc"));
+    assert!(promotion::matches("user.?d", "user.id"));
+    assert!(!promotion::matches("score", "scores"));
+    assert!(!promotion::matches("tmp_*", "temp_x"));
+    assert!(!promotion::matches("a*b", "abc"));
+}
+
+#[test]
+fn test_merge_strategies() {
+    let merge = |strategy: MergeStrategy, a: &str, b: &str| {
+        strategy.merge(&Value::from_literal(a), &Value::from_literal(b)).map(|v| v.to_string())
+    };
+    assert_eq!(merge(MergeStrategy::Overwrite, "1", "2"), Ok("2".into()));
+    assert_eq!(merge(MergeStrategy::Keep, "1", "2"), Ok("1".into()));
+    assert_eq!(merge(MergeStrategy::Sum, "1", "2"), Ok("3".into()));
+    assert_eq!(merge(MergeStrategy::Sum, "1", "0.5"), Ok("1.5".into()));
+    assert_eq!(merge(MergeStrategy::Max, "7", "2"), Ok("7".into()));
+    assert_eq!(merge(MergeStrategy::Max, "\"apple\"", "\"pear\""), Ok("pear".into()));
+    assert_eq!(merge(MergeStrategy::Append, "[1]", "[2,3]"), Ok("[1,2,3]".into()));
+    assert_eq!(merge(MergeStrategy::Append, "[1]", "2"), Ok("[1,2]".into()));
+    assert_eq!(merge(MergeStrategy::Append, "1", "2"), Ok("[1,2]".into()));
+    assert!(merge(MergeStrategy::Sum, "1", "\"x\"").is_err());
+
+    assert_eq!("SUM".parse::<MergeStrategy>(), Ok(MergeStrategy::Sum));
+    assert!("average".parse::<MergeStrategy>().is_err());
+}
+
+#[test]
+fn test_sum_widens_past_i64() {
+    let max = Value::Int(i64::MAX);
+    assert_eq!(MergeStrategy::Sum.merge(&max, &Value::Int(1)), Ok(Value::BigInt(i64::MAX as i128 + 1)));
+    assert_eq!(
+        MergeStrategy::Sum.merge(&Value::BigInt(i64::MAX as i128 + 1), &Value::Int(-1)),
+        Ok(max)
+    );
+    assert!(MergeStrategy::Sum.merge(&Value::BigInt(i128::MAX), &Value::Int(1)).is_err());
+}
+
+#[test]
+fn test_policy_filters_and_merges_scope_promotion() {
+    let mut memory = MemoryStore::new();
+    memory.set("score", Value::Int(10), None);
+    memory.set("name", Value::from("old"), None);
+    memory.enter_scope("tick:1", None);
+    memory.set("score", Value::Int(5), None);
+    memory.set("name", Value::from("new"), None);
+    memory.set("tmp_total", Value::Int(1), None);
+    memory.set("label", Value::Int(1), None);
+
+    memory.set_promotion_policy(PromotionPolicy {
+        deny: vec!["tmp_*".into()],
+        rules: vec![("score".into(), MergeStrategy::Sum), ("label".into(), MergeStrategy::Append)],
+        merge: MergeStrategy::Keep,
+        ..PromotionPolicy::default()
+    });
+    let promotions = memory.promote_scope("tick:1", "global", None);
+    memory.exit_scope(None);
+
+    assert_eq!(memory.get("score"), Some(Value::Int(15)));
+    assert_eq!(memory.get("name"), Some(Value::from("old")));
+    assert_eq!(memory.get("label"), Some(Value::Int(1)));
+    assert_eq!(memory.get("tmp_total"), None);
+
+    let keys: Vec<&str> = promotions.iter().map(|p| p.key.as_str()).collect();
+    assert_eq!(keys, ["label", "name", "score", "tmp_total"]);
+    assert_eq!(promotions[3].outcome, Outcome::Denied("denied by 'tmp_*'".into()));
+    assert_eq!(promotions[2].to_string(), "score: [tick:1] → [global] = 15 (sum)");
+}
+
+#[test]
+fn test_allow_list_limits_promotion() {
+    let mut memory = MemoryStore::new();
+    memory.set_promotion_policy(PromotionPolicy { allow: vec!["user_*".into()], ..PromotionPolicy::default() });
+    memory.enter_scope("event:start", None);
+    memory.set("user_id", Value::Int(7), None);
+    memory.set("draft", Value::Int(1), None);
+    let promotions = memory.promote_all();
+    memory.exit_scope(None);
+
+    assert_eq!(memory.get("user_id"), Some(Value::Int(7)));
+    assert_eq!(memory.get("draft"), None);
+    assert!(matches!(&promotions[0].outcome, Outcome::Denied(reason) if reason == "not in the allow list"));
+}
+
+#[test]
+fn test_promote_statement_with_condition() {
+    let responses = run(
+        "on bump\n  load 5 as score\n  load 1 as scratch\n  promote score, scratch to global when score > 3\non start\n  trigger bump\n  respond \"{{score}} {{scratch}}\"\n",
+    );
+    assert_eq!(responses, ["\"5 1\""]);
+
+    let responses = run(
+        "on bump\n  load 2 as score\n  promote score to global when score > 3\non start\n  load 0 as score to global\n  trigger bump\n  respond \"{{score}}\"\n",
+    );
+    assert_eq!(responses, ["\"0\""]);
+}
+
+#[test]
+fn test_promote_statement_uses_policy_settings() {
+    let responses = run(
+        "set promote_merge sum for points\nset promote_deny secret_*\non earn\n  load 3 as points\n  load 1 as secret_key\n  promote * to global\non start\n  load 10 as points to global\n  trigger earn\n  trigger earn\n  respond \"{{points}}\"\n  respond_event \"{{secret_key}}\"\n",
+    );
+    assert_eq!(responses, ["\"16\"", "0"]);
+}
+
+#[test]
+fn test_promotions_appear_in_memory_trace() {
+    let mut runtime = Runtime::from_source(
+        "set promote_merge max\non raise\n  load 4 as best\n  promote best\non start\n  load 9 as best to global\n  trigger raise\n",
+        false,
+    )
+    .unwrap();
+    runtime.run();
+    let trace = runtime.query("trace: memory best").unwrap();
+    assert!(trace.contains("best = 9 in [global] at tick 0 promoted from [event:raise] (max) by 4:3"), "{}", trace);
+}
+
+#[test]
+fn test_promote_to_missing_scope_fails() {
+    let responses = run(
+        "on start\n  load 1 as a\n  atomic\n    load 2 as b to global\n    promote a to nowhere\n  respond \"{{b}}\"\n",
+    );
+    assert_eq!(responses, ["\"0\""]);
+}
+
+#[test]
+fn test_malformed_promotion_is_rejected() {
+    assert!(Runtime::from_source("on start\n  promote\n", false).is_err());
+    assert!(Runtime::from_source("on start\n  promote a when\n", false).is_err());
+    assert!(Runtime::from_source("on start\n  promote a to\n", false).is_err());
+    assert!(Runtime::from_source("set promote_merge average\n", false).is_err());
+    assert!(Runtime::from_source("set promote_allow\n", false).is_err());
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::run;
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::runtime::value::Value;
+
+#[test]
+fn test_stop_halts_enclosing_handler() {
+    let mut runtime = Runtime::from_source(
+        "on login\n  verify token\n  if invalid\n    respond \"Unauthorized\"\n    stop\n  respond \"Welcome\"\n",
+        false,
+    )
+    .unwrap();
+    runtime.trigger_event("login");
+    assert_eq!(runtime.responses(), ["\"Unauthorized\""]);
+}
+
+#[test]
+fn test_stop_unwinds_nested_ifs() {
+    let responses = run(
+        "on start\n  if \"(1 == 1)\"\n    if \"(2 > 1)\"\n      respond \"inner\"\n      stop\n      respond \"after stop\"\n    respond \"outer tail\"\n  respond \"handler tail\"\n",
+    );
+    assert_eq!(responses, ["\"inner\""]);
+}
+
+#[test]
+fn test_false_branch_does_not_stop() {
+    let responses = run(
+        "on start\n  if \"(1 == 2)\"\n    respond \"never\"\n    stop\n  respond \"reached\"\n",
+    );
+    assert_eq!(responses, ["\"reached\""]);
+}
+
+#[test]
+fn test_stop_in_triggered_handler_returns_to_caller() {
+    let responses = run(
+        "on start\n  trigger check\n  respond \"start continues\"\non check\n  respond \"checking\"\n  stop\n  respond \"unreachable\"\n",
+    );
+    assert_eq!(responses, ["\"checking\"", "\"start continues\""]);
+}
+
+#[test]
+fn test_top_level_stop_drains_queue() {
+    let responses = run("respond \"first\"\nstop\nrespond \"second\"\n");
+    assert_eq!(responses, ["\"first\""]);
+}
+
+#[test]
+fn test_else_runs_only_when_condition_false() {
+    let responses = run(
+        "on start\n  if \"(1 == 1)\"\n    respond \"then\"\n  else\n    respond \"else\"\n  unless \"(1 == 1)\"\n    respond \"unless\"\n  else\n    respond \"unless else\"\n",
+    );
+    assert_eq!(responses, ["\"then\"", "\"unless else\""]);
+}
+
+#[test]
+fn test_else_if_chain_picks_first_match() {
+    let responses = run(
+        "load 2 as n\nif \"({{n}} == 1)\"\n  respond \"one\"\nelse if \"({{n}} == 2)\"\n  respond \"two\"\nelse\n  respond \"many\"\n",
+    );
+    assert_eq!(responses, ["\"two\""]);
+}
+
+#[test]
+fn test_while_loop_with_break_and_continue() {
+    let responses = run(
+        "on start\n  load 0 as i\n  while \"({{i}} < 10)\"\n    load \"{{i}} + 1\" as i\n    if \"({{i}} == 2)\"\n      continue\n    if \"({{i}} == 5)\"\n      break\n    respond_event \"{{i}}\"\n  respond \"done\"\n",
+    );
+    assert_eq!(responses, ["1", "3", "4", "\"done\""]);
+}
+
+#[test]
+fn test_while_respects_iteration_cap() {
+    let responses = run(
+        "set max_iterations 3\non start\n  while \"(1 == 1)\"\n    respond \"spin\"\n  respond \"escaped\"\n",
+    );
+    assert_eq!(responses, ["\"spin\"", "\"spin\"", "\"spin\"", "\"escaped\""]);
+}
+
+#[test]
+fn test_stop_inside_loop_ends_handler() {
+    let responses = run(
+        "on start\n  while \"(1 == 1)\"\n    respond \"once\"\n    stop\n  respond \"unreachable\"\n",
+    );
+    assert_eq!(responses, ["\"once\""]);
+}
+
+#[test]
+fn test_break_outside_loop_is_rejected() {
+    assert!(Runtime::from_source("on start\n  break\n", false).is_err());
+}
+
+#[test]
+fn test_multi_word_event_with_payload() {
+    let responses = run(
+        "on start\n  load 41 as base\n  trigger \"user login\" with user_id \"{{base}} + 1\" attempts 3\n\non user login\n  respond \"welcome\"\n  respond_event \"{{user_id}} * {{attempts}}\"\n",
+    );
+    assert_eq!(responses, ["\"welcome\"", "126"]);
+}
+
+#[test]
+fn test_missing_handler_receives_payload() {
+    let responses = run(
+        "on start\n  trigger user logout with code 3\n\non missing\n  respond_event \"{{code}} + 1\"\n",
+    );
+    assert_eq!(responses, ["4"]);
+}
+
+#[test]
+fn test_trigger_payload_from_host() {
+    let mut runtime = Runtime::from_source("on order placed\n  respond_event \"{{order}} + 0\"\n", false).unwrap();
+    runtime.trigger_event_with("order placed", &[("order".to_string(), Value::Int(7))]);
+    assert_eq!(runtime.responses(), ["7"]);
+}
+
+#[test]
+fn test_trigger_payload_key_without_value_is_rejected() {
+    assert!(Runtime::from_source("on start\n  trigger done with user_id\n", false).is_err());
+}
@@ -0,0 +1,122 @@
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::interpreter::math::OverflowPolicy;
+use idc_runtime::runtime::snapshot::{Snapshot, SnapshotError};
+use idc_runtime::runtime::value::Value;
+use std::path::PathBuf;
+
+const COUNTER: &str = "set overflow saturate\non start\n  load 0 as count to global\nevery 1\n  load \"{{count}} + 1\" as count to global\nevery 3\n  respond \"count {{count}}\"\n";
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("idc_{}_{}.json", name, std::process::id()))
+}
+
+#[test]
+fn test_snapshot_resumes_a_tick_simulation() {
+    let mut original = Runtime::from_source(COUNTER, false).unwrap();
+    original.run();
+    for _ in 0..4 {
+        original.tick();
+    }
+    let snapshot = Snapshot::from_json(&original.snapshot().to_json().unwrap()).unwrap();
+    assert_eq!(snapshot.tick, 4);
+    assert_eq!(snapshot.scopes[0].data.get("count"), Some(&Value::Int(4)));
+    assert_eq!(snapshot.flags.overflow, OverflowPolicy::Saturate);
+
+    let mut resumed = Runtime::from_source(COUNTER, false).unwrap();
+    resumed.restore(snapshot).unwrap();
+    for _ in 0..2 {
+        original.tick();
+        resumed.tick();
+    }
+    assert_eq!(resumed.responses(), ["\"count 6\""]);
+    assert_eq!(original.responses().last(), resumed.responses().last());
+    assert_eq!(original.snapshot(), resumed.snapshot());
+}
+
+#[test]
+fn test_save_and_load_memory_statements() {
+    let path = temp_file("statements");
+    let path = path.to_str().unwrap();
+
+    let mut saver = Runtime::from_source(
+        &format!("on start\n  load {{\"hp\":7}} as player to global\n  set strict true\n  save memory to \"{}\"\n", path),
+        false,
+    )
+    .unwrap();
+    saver.run();
+    assert!(std::fs::metadata(path).is_ok(), "save memory should write the file");
+
+    let mut loader = Runtime::from_source(
+        &format!("on start\n  load memory from \"{}\"\n  respond \"hp {{{{player.hp}}}}\"\n  respond \"{{{{nope}}}}\"\n", path),
+        false,
+    )
+    .unwrap();
+    loader.run();
+    assert_eq!(loader.responses(), ["\"hp 7\""], "the loaded strict setting rejects the unknown key");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_invalid_snapshots_are_rejected() {
+    let mut snapshot = Runtime::new(false).snapshot();
+    snapshot.version = 99;
+    assert!(matches!(Snapshot::from_json(&snapshot.to_json().unwrap()), Err(SnapshotError::Version(99))));
+
+    let mut snapshot = Runtime::new(false).snapshot();
+    snapshot.scopes.clear();
+    assert!(matches!(Runtime::new(false).restore(snapshot), Err(SnapshotError::MissingGlobal)));
+
+    assert!(matches!(Snapshot::from_json("{}"), Err(SnapshotError::Format(_))));
+    assert!(matches!(Snapshot::load_from(temp_file("missing")), Err(SnapshotError::Io(_))));
+
+    let mut runtime = Runtime::from_source("on start\n  load memory from \"/nonexistent/state.json\"\n  respond \"still running\"\n", false).unwrap();
+    runtime.run();
+    assert_eq!(runtime.responses(), ["\"still running\""]);
+    assert!(Runtime::from_source("on start\n  save memory\n", false).is_err());
+}
+
+#[test]
+fn test_restoring_a_script_snapshot_drops_handler_frames() {
+    let path = temp_file("handler_frames");
+    let path = path.to_str().unwrap();
+    let script = format!(
+        "on start\n  load \"{{{{runs}}}} + 1\" as runs to global\n  respond \"run {{{{runs}}}}\"\n  save memory to \"{}\"\n",
+        path
+    );
+
+    let mut first = Runtime::from_source(&script, false).unwrap();
+    first.run();
+    let saved = Snapshot::load_from(path).unwrap();
+    assert_eq!(saved.event_stack, ["start"], "the snapshot keeps the full stack it was taken with");
+    assert_eq!(saved.scopes.len(), 2);
+
+    let mut second = Runtime::from_source(&script, false).unwrap();
+    second.restore(saved).unwrap();
+    let restored = second.snapshot();
+    assert_eq!(restored.scopes.len(), 1);
+    assert!(restored.event_stack.is_empty());
+
+    second.run();
+    assert_eq!(second.responses(), ["\"run 2\""], "`on start` fires again after a restore");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_loaded_values_appear_in_memory_trace() {
+    let path = temp_file("trace");
+    let path = path.to_str().unwrap();
+    let mut runtime = Runtime::from_source(
+        &format!(
+            "on start\n  load 1 as balance to global\n  save memory to \"{0}\"\n  load 3 as balance to global\n  load memory from \"{0}\"\n",
+            path
+        ),
+        false,
+    )
+    .unwrap();
+    runtime.run();
+
+    let trace = runtime.query("trace: balance").unwrap();
+    let last = trace.lines().last().unwrap();
+    assert!(last.contains(&format!("balance = 1 in [global] at tick 0 restored from {} by 5:3", path)), "{}", trace);
+    std::fs::remove_file(path).unwrap();
+}
@@ -1,18 +1,33 @@
-use crate::parser::tokenizer::{tokenize_file, Token};
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::parser::ast::{build_ast_from_reader, build_ast_from_str};
+use idc_runtime::parser::semantic::analyze_semantics;
+use idc_runtime::parser::span::Span;
+use idc_runtime::parser::token_kind::{TokenCategory, TokenKind};
+use idc_runtime::parser::tokenizer::{tokenize_file, tokenize_reader, tokenize_str};
+
+const BASIC_IDC: &str = r#"on "start"
+  respond Start complete"
+  load 5 as counter
+  remember user_id
+  if "{{counter}} > 3"
+    respond "Counter is high"
+  unless "{{counter}} > 10"
+    respond "Counter is low"
+  trigger "finish"
+
+on "finish"
+  respond "Done"
+"#;
 
 #[test]
 fn test_tokenize_basic_idc() {
     // Call the tokenizer function
-    let result = tokenize_file("examples/basic.idc");
-    assert!(result.is_ok(), "Failed to tokenize file");
-
-    // Get the tokens
-    let tokens = result.unwrap();
-    assert!(tokens.len() > 0, "No tokens were generated");
+    let tokens = tokenize_str(BASIC_IDC);
+    assert!(!tokens.is_empty(), "No tokens were generated");
 
     // Test that the first token is 'on'
     let first_token = &tokens[0];
-    assert_eq!(first_token.token_type, "on", "First token should be 'on'");
+    assert_eq!(first_token.token_type, TokenKind::On, "First token should be 'on'");
     assert_eq!(first_token.value, "\"start\"", "First token value should be '\"start\"'");
 
     // Test token count (optional based on your expectations)
@@ -20,38 +35,32 @@ fn test_tokenize_basic_idc() {
 
     // Test a specific token (optional)
     let second_token = &tokens[1];
-    assert_eq!(second_token.token_type, "respond", "Second token should be 'respond'");
+    assert_eq!(second_token.token_type, TokenKind::Respond, "Second token should be 'respond'");
     assert_eq!(second_token.modifiers, vec!["complete\""], "Second token should have the correct modifier");
 }
 
 #[test]
 fn test_true_and_false_conditions() {
     // Test for tokens "true" and "false" being correctly identified as conditions
-    let result = tokenize_file("examples/true_false.idc");
-    assert!(result.is_ok(), "Failed to tokenize file");
-
-    let tokens = result.unwrap();
+    let tokens = tokenize_str("true\nfalse\n");
 
     let first_token = &tokens[0];
-    assert_eq!(first_token.token_type, "true", "First token should be 'true'");
+    assert_eq!(first_token.token_type, TokenKind::True, "First token should be 'true'");
 
     let second_token = &tokens[1];
-    assert_eq!(second_token.token_type, "false", "Second token should be 'false'");
+    assert_eq!(second_token.token_type, TokenKind::False, "Second token should be 'false'");
 }
 
 #[test]
 fn test_else_condition() {
     // Test for 'else' being correctly classified
-    let result = tokenize_file("examples/else_condition.idc");
-    assert!(result.is_ok(), "Failed to tokenize file");
-
-    let tokens = result.unwrap();
+    let tokens = tokenize_str("if \"{{ready}}\"\nelse\n");
 
     let first_token = &tokens[0];
-    assert_eq!(first_token.token_type, "if", "First token should be 'if'");
+    assert_eq!(first_token.token_type, TokenKind::If, "First token should be 'if'");
 
     let second_token = &tokens[1];
-    assert_eq!(second_token.token_type, "else", "Second token should be 'else'");
+    assert_eq!(second_token.token_type, TokenKind::Else, "Second token should be 'else'");
 }
 
 #[test]
@@ -60,3 +69,91 @@ fn test_invalid_tokenize() {
     let result = tokenize_file("examples/invalid.idc");
     assert!(result.is_err(), "Tokenization should fail for an invalid file");
 }
+
+#[test]
+fn test_tokenize_reader_matches_str() {
+    let from_reader = tokenize_reader(BASIC_IDC.as_bytes()).expect("in-memory reader");
+    let from_str = tokenize_str(BASIC_IDC);
+    assert_eq!(from_reader.len(), from_str.len());
+    for (a, b) in from_reader.iter().zip(&from_str) {
+        assert_eq!(a.token_type, b.token_type);
+        assert_eq!(a.value, b.value);
+        assert_eq!(a.indentation, b.indentation);
+    }
+}
+
+#[test]
+fn test_build_ast_from_source() {
+    let ast = build_ast_from_str(BASIC_IDC).expect("AST should build");
+    assert_eq!(ast.len(), 2, "Expected two top-level event handlers");
+    assert_eq!(ast[0].children.len(), 6);
+
+    let from_reader = build_ast_from_reader(BASIC_IDC.as_bytes()).expect("AST should build");
+    assert_eq!(from_reader.len(), ast.len());
+}
+
+#[test]
+fn test_runtime_from_source() {
+    assert!(Runtime::from_source(BASIC_IDC, false).is_ok());
+    assert!(Runtime::from_source("", false).is_err(), "Empty source has no AST");
+}
+
+#[test]
+fn test_token_spans() {
+    let source = "on start\n\n  respond \"hi\"\r\n    load 1 as x\n";
+    let tokens = tokenize_str(source);
+
+    assert_eq!(tokens[0].span, Span::new(1, 1, 0, 8));
+    assert_eq!(tokens[1].span, Span::new(3, 3, 12, 24));
+    assert_eq!(&source[tokens[1].span.start..tokens[1].span.end], "respond \"hi\"");
+    assert_eq!(tokens[2].span.line, 4);
+    assert_eq!(tokens[2].span.column, 5);
+    assert_eq!(&source[tokens[2].span.start..tokens[2].span.end], "load 1 as x");
+}
+
+#[test]
+fn test_semantic_warnings_carry_spans() {
+    let ast = build_ast_from_str("on start\n  if\n  respond\n").expect("AST should build");
+    assert_eq!(ast[0].children[0].span.line, 2);
+
+    let warnings: Vec<String> = analyze_semantics(&ast)
+        .iter()
+        .map(|w| format!("script.idc:{}", w))
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "script.idc:2:3: if block has no body",
+            "script.idc:3:3: `respond` used with no value",
+        ]
+    );
+}
+
+#[test]
+fn test_token_kind_round_trip() {
+    for kind in TokenKind::KEYWORDS {
+        let text = kind.to_string();
+        assert_eq!(text.parse::<TokenKind>(), Ok(*kind));
+        assert!(kind.category().is_some(), "{} should have a category", text);
+    }
+    assert_eq!("unknown".parse::<TokenKind>(), Ok(TokenKind::Unknown));
+    assert!("respnd".parse::<TokenKind>().is_err());
+}
+
+#[test]
+fn test_token_kind_categories() {
+    assert_eq!(TokenKind::On.category(), Some(TokenCategory::Event));
+    assert_eq!(TokenKind::Unless.category(), Some(TokenCategory::Condition));
+    assert_eq!(TokenKind::With.category(), Some(TokenCategory::Modifier));
+    assert_eq!(TokenKind::Remember.category(), Some(TokenCategory::Memory));
+    assert_eq!(TokenKind::As.category(), Some(TokenCategory::Memory), "the glossary files `as` under Memory");
+    assert_eq!(TokenKind::Purge.category(), Some(TokenCategory::System));
+    assert_eq!(TokenKind::Stop.category(), Some(TokenCategory::Flow));
+    assert_eq!(TokenKind::Route.category(), Some(TokenCategory::Entity));
+    assert_eq!(TokenKind::Respond.category(), Some(TokenCategory::Action));
+    assert_eq!(TokenKind::Unknown.category(), None);
+
+    let tokens = tokenize_str("respond_event \"1 + 1\"\nfrobnicate now\n");
+    assert_eq!(tokens[0].token_type, TokenKind::RespondEvent);
+    assert_eq!(tokens[1].token_type, TokenKind::Unknown);
+}
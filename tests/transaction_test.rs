@@ -0,0 +1,105 @@
+mod common;
+
+use common::run;
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::runtime::memory::MemoryStore;
+use idc_runtime::runtime::value::Value;
+
+#[test]
+fn test_atomic_block_commits_when_it_completes() {
+    let responses = run(
+        "on start\n  load 1 as balance to global\n  atomic\n    load \"{{balance}} + 10\" as balance to global\n    load 2 as fee\n  respond \"{{balance}} {{fee}}\"\n",
+    );
+    assert_eq!(responses, ["\"11 2\""]);
+}
+
+#[test]
+fn test_rollback_discards_writes() {
+    let responses = run(
+        "on start\n  load 1 as balance to global\n  atomic\n    load 99 as balance to global\n    load 5 as fee\n    if balance > 50, rollback\n    respond \"unreachable\"\n  respond \"{{balance}} {{fee}}\"\n",
+    );
+    assert_eq!(responses, ["\"1 0\""]);
+}
+
+#[test]
+fn test_stop_rolls_back_and_ends_the_handler() {
+    let mut runtime = Runtime::from_source(
+        "on start\n  load \"before\" as state to global\n  atomic\n    load \"during\" as state to global\n    stop\n  respond \"unreachable\"\n",
+        false,
+    )
+    .unwrap();
+    runtime.run();
+    assert!(runtime.responses().is_empty());
+    assert_eq!(runtime.snapshot().scopes[0].data.get("state"), Some(&Value::from("before")));
+}
+
+#[test]
+fn test_failed_statement_rolls_back_a_triggered_handler() {
+    let responses = run(
+        "on user login\n  load \"{{logins}} + 1\" as logins to global\n  load \"1 +\" as broken\n  respond \"unreachable\"\non start\n  load 0 as logins to global\n  atomic\n    trigger user login\n    respond \"unreachable too\"\n  respond \"logins {{logins}}\"\n",
+    );
+    assert_eq!(responses, ["\"logins 0\""]);
+}
+
+#[test]
+fn test_atomic_inline_and_nested() {
+    let responses = run(
+        "on start\n  atomic, load 3 as a to global\n  atomic\n    load 4 as b to global\n    atomic\n      load 5 as c to global\n      rollback\n  respond \"{{a}} {{b}} {{c}}\"\n",
+    );
+    assert_eq!(responses, ["\"3 4 0\""]);
+}
+
+#[test]
+fn test_rollback_outside_atomic_is_rejected() {
+    assert!(Runtime::from_source("on start\n  rollback\n", false).is_err());
+    assert!(Runtime::from_source("on start\n  atomic\n    on nested\n      rollback\n", false).is_err());
+}
+
+#[test]
+fn test_skipped_statements_name_the_rollback() {
+    let mut runtime =
+        Runtime::from_source("on start\n  atomic\n    rollback\n    respond \"later\"\n", false).unwrap();
+    runtime.run();
+    let answer = runtime.query("why: did it skip \"respond later\"").unwrap();
+    assert!(answer.contains("`rollback` at 3:5"), "{}", answer);
+}
+
+#[test]
+fn test_memory_store_transactions() {
+    let mut memory = MemoryStore::new();
+    memory.set("kept", Value::Int(1), None);
+    memory.begin();
+    memory.set("kept", Value::Int(2), None);
+    memory.set("added", Value::Int(3), None);
+    assert!(memory.in_transaction());
+    assert_eq!(memory.rollback(), 2);
+    assert_eq!(memory.get("kept"), Some(Value::Int(1)));
+    assert_eq!(memory.get("added"), None);
+
+    memory.begin();
+    memory.set("added", Value::Int(3), None);
+    memory.commit();
+    assert!(!memory.in_transaction());
+    assert_eq!(memory.get("added"), Some(Value::Int(3)));
+}
+
+#[test]
+fn test_rollback_drops_discarded_writes_from_trace() {
+    let mut runtime = Runtime::from_source(
+        "on start\n  load 40 as balance to global\n  atomic\n    load 50 as balance to global\n    load 60 as balance to global\n    rollback\n",
+        false,
+    )
+    .unwrap();
+    runtime.run();
+    let trace = runtime.query("trace: balance").unwrap();
+    assert!(trace.contains("balance = 40"), "{}", trace);
+    assert!(!trace.contains("balance = 50") && !trace.contains("balance = 60"), "{}", trace);
+}
+
+#[test]
+fn test_failed_path_write_rolls_back_atomic_block() {
+    let responses = run(
+        "on start\n  load 5 as total to global\n  load 1 as count to global\n  atomic\n    load 9 as total to global\n    load 2 as count.value to global\n  respond \"{{total}}\"\n",
+    );
+    assert_eq!(responses, ["\"5\""]);
+}
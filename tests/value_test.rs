@@ -0,0 +1,103 @@
+mod common;
+
+use common::run;
+use idc_runtime::interpreter::engine::Runtime;
+use idc_runtime::runtime::value::Value;
+use std::collections::BTreeMap;
+
+#[test]
+fn test_literals_parse_to_typed_values() {
+    assert_eq!(Value::from_literal("42"), Value::Int(42));
+    assert_eq!(Value::from_literal("-7"), Value::Int(-7));
+    assert_eq!(Value::from_literal("2.5"), Value::Float(2.5));
+    assert_eq!(Value::from_literal("true"), Value::Bool(true));
+    assert_eq!(Value::from_literal("null"), Value::Null);
+    assert_eq!(Value::from_literal("\"42\""), Value::Str("42".into()));
+    assert_eq!(Value::from_literal("hot"), Value::Str("hot".into()));
+    assert_eq!(Value::from_literal("inf"), Value::Str("inf".into()));
+}
+
+#[test]
+fn test_truthiness() {
+    assert!(Value::Str("hot".into()).is_truthy());
+    assert!(!Value::Str(String::new()).is_truthy());
+    assert!(!Value::Int(0).is_truthy());
+    assert!(Value::Float(0.5).is_truthy());
+    assert!(!Value::Null.is_truthy());
+    assert!(!Value::List(vec![]).is_truthy());
+    assert!(Value::List(vec![Value::Null]).is_truthy());
+}
+
+#[test]
+fn test_values_round_trip_through_json() {
+    let mut user = BTreeMap::new();
+    user.insert("id".to_string(), Value::Int(7));
+    user.insert("roles".to_string(), Value::List(vec![Value::from("admin"), Value::Null]));
+    user.insert("score".to_string(), Value::Float(1.5));
+    user.insert("active".to_string(), Value::Bool(true));
+    let value = Value::Map(user);
+
+    let json = serde_json::to_string(&value).unwrap();
+    assert_eq!(json, r#"{"active":true,"id":7,"roles":["admin",null],"score":1.5}"#);
+    assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+    assert_eq!(value.to_string(), json);
+    assert_eq!(Value::Float(3.0).to_string(), "3.0");
+}
+
+#[test]
+fn test_text_value_is_truthy_in_conditions() {
+    let responses = run(
+        "on start\n  load hot as status\n  if \"{{status}}\"\n    respond \"warm\"\n  else\n    respond \"cold\"\n",
+    );
+    assert_eq!(responses, ["\"warm\""]);
+}
+
+#[test]
+fn test_placeholders_render_typed_values() {
+    let responses = run(
+        "on start\n  load Ada as name\n  load 3 as count\n  respond \"{{name}} has {{count}} and {{2 * 3}}\"\n",
+    );
+    assert_eq!(responses, ["\"Ada has 3 and 6\""]);
+}
+
+#[test]
+fn test_comparisons_produce_booleans() {
+    let responses = run("on start\n  respond_event \"(2 > 1)\"\n  respond_event \"(2 == 2) + 1\"\n");
+    assert_eq!(responses, ["true"], "adding a number to a bool is an error, not 2");
+}
+
+#[test]
+fn test_inner_scope_shadows_outer_in_expressions() {
+    let responses = run(
+        "on start\n  load 1 as count to global\n  trigger bump with count 5\n\non bump\n  respond_event \"{{count}} + 1\"\n",
+    );
+    assert_eq!(responses, ["6"]);
+}
+
+#[test]
+fn test_float_literals_and_promotion() {
+    let responses = run(
+        "on start\n  load 10 as price\n  respond_event \"{{price}} * 1.5\"\n  respond_event \"7 / 2\"\n  respond_event \"7 / 2.0\"\n  respond_event \"0.5 + 0.25\"\n  respond_event \"(2.5 > 2)\"\n  respond_event \"(2.0 == 2)\"\n",
+    );
+    assert_eq!(responses, ["15.0", "3", "3.5", "0.75", "true", "true"]);
+}
+
+#[test]
+fn test_precision_setting_formats_floats() {
+    let responses = run(
+        "set precision 2\non start\n  load 19.99 as price\n  respond_event \"{{price}} * 1.2\"\n  respond_event \"{{price}} * 3\"\n  respond_event \"7 / 2\"\n  set precision auto\n  respond_event \"1 / 3.0\"\n",
+    );
+    assert_eq!(responses, ["23.99", "59.97", "3", "0.3333333333333333"]);
+}
+
+#[test]
+fn test_invalid_precision_is_rejected() {
+    assert!(Runtime::from_source("set precision many\n", false).is_err());
+    assert!(Runtime::from_source("set precision 40\n", false).is_err());
+}
+
+#[test]
+fn test_float_division_by_zero_is_reported() {
+    let responses = run("on start\n  respond_event \"1.5 / 0\"\n  respond \"after\"\n");
+    assert_eq!(responses, ["\"after\""]);
+}